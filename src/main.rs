@@ -5,18 +5,55 @@ pub mod traits;
 use models::structs::*;
 use services::actions::*;
 use services::n::*;
+use services::operations::set_dry_run;
+use std::process::exit;
 use structopt::StructOpt;
 
+/// Actions that run scripts or write outside of the operations `--dry-run` can skip.
+const DRY_RUN_UNSUPPORTED: [&str; 6] = ["build", "test", "run", "exec", "watch", "cache"];
+/// Actions taking a second positional argument after the project.
+const TARGET_ACTIONS: [&str; 2] = ["why", "run"];
+
 pub fn main() {
     let args = Params::from_args();
+    if args.dry_run && DRY_RUN_UNSUPPORTED.contains(&&args.action[..]) {
+        println!("Option --dry-run is not supported by {}!", args.action);
+        exit(1);
+    }
+    if !args.target.is_empty() && !TARGET_ACTIONS.contains(&&args.action[..]) {
+        println!(
+            "Unexpected argument {} for {}! Use --node-version to switch node.",
+            args.target, args.action
+        );
+        exit(1);
+    }
+    set_dry_run(args.dry_run);
     set_node_version(&args.node_version);
     match &args.action[..] {
-        "link" => link(&args.project, &args.path),
-        "copy" => copy(&args.project, &args.path),
-        "unlink" => unlink(&args.project, &args.path),
-        "update" => update(&args.project, &args.path),
-        "clean" => clean(&args.project, &args.path),
-        "build" => build(&args.project, &args.path),
+        "link" => link(&args),
+        "copy" => copy(&args),
+        "unlink" => unlink(&args),
+        "pack" => pack(&args),
+        "unpack" => unpack(&args),
+        "sync" => sync(&args),
+        "restore" => restore(&args),
+        "recover" => recover(&args),
+        "update" => update(&args),
+        "clean" => clean(&args),
+        "build" => build(&args),
+        "test" => test(&args),
+        "run" => run(&args),
+        "exec" => exec(&args),
+        "watch" => watch(&args),
+        "status" => status(&args),
+        "graph" => graph(&args),
+        "dependents" => dependents(&args),
+        "affected" => affected(&args),
+        "why" => why(&args),
+        "cache" => cache(&args),
         _ => println!("Command not found!"),
     }
+    if args.dry_run {
+        println!("\nDry run, nothing was changed.");
+    }
 }
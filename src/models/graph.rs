@@ -0,0 +1,428 @@
+use crate::models::structs::{Dependency, RepositoryItem};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+pub struct DependencyCycle {
+    pub path: Vec<String>,
+}
+
+impl fmt::Display for DependencyCycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.join(" -> "))
+    }
+}
+
+pub struct TraversalPlan {
+    pub root: String,
+    pub projects: Vec<String>,
+    pub visits: usize,
+}
+
+impl TraversalPlan {
+    /// Visits a per-path traversal would have repeated on shared dependencies.
+    pub fn saved_visits(&self) -> usize {
+        self.visits - self.projects.len()
+    }
+
+    /// Planned projects without the root one.
+    pub fn dependencies(&self) -> Vec<&String> {
+        self.projects.iter().filter(|p| **p != self.root).collect()
+    }
+}
+
+pub struct DependencyGraph {
+    items: HashMap<String, RepositoryItem>,
+    modules: HashMap<String, String>,
+    dependents: HashMap<String, Vec<String>>,
+}
+
+impl DependencyGraph {
+    pub fn new(items: Vec<RepositoryItem>) -> Self {
+        let modules: HashMap<String, String> = items
+            .iter()
+            .map(|ri| (ri.module.to_string(), ri.project.to_string()))
+            .collect();
+        let mut dependents = HashMap::<String, Vec<String>>::new();
+        items.iter().for_each(|ri| {
+            ri.dependencies
+                .iter()
+                .filter_map(|dep| modules.get(&dep.module))
+                .for_each(|dep_project| {
+                    dependents
+                        .entry(dep_project.to_string())
+                        .or_default()
+                        .push(ri.project.to_string());
+                });
+        });
+        dependents.values_mut().for_each(|d| d.sort());
+        let items = items
+            .into_iter()
+            .map(|ri| (ri.project.to_string(), ri))
+            .collect();
+        Self {
+            items,
+            modules,
+            dependents,
+        }
+    }
+
+    /// Every project of the repository, sorted by path.
+    pub fn projects(&self) -> Vec<&RepositoryItem> {
+        let mut projects: Vec<&RepositoryItem> = self.items.values().collect();
+        projects.sort_by(|a, b| a.project.cmp(&b.project));
+        projects
+    }
+
+    pub fn get(&self, project: &str) -> Option<&RepositoryItem> {
+        self.items.get(project)
+    }
+
+    pub fn get_by_module(&self, module: &str) -> Option<&RepositoryItem> {
+        self.modules.get(module).and_then(|p| self.items.get(p))
+    }
+
+    /// Resolves a project path or a package name to its repository item.
+    pub fn find(&self, project_or_module: &str) -> Option<&RepositoryItem> {
+        self.get(project_or_module)
+            .or_else(|| self.get_by_module(project_or_module))
+    }
+
+    /// Internal dependencies of a project, in declaration order.
+    pub fn dependencies(&self, project: &str) -> Vec<&RepositoryItem> {
+        self.edges(project).into_iter().map(|(_, ri)| ri).collect()
+    }
+
+    /// Internal edges of a project, each with the item it points to.
+    pub fn edges(&self, project: &str) -> Vec<(&Dependency, &RepositoryItem)> {
+        self.items.get(project).map_or_else(Vec::new, |ri| {
+            ri.dependencies
+                .iter()
+                .filter_map(|dep| self.get_by_module(&dep.module).map(|d| (dep, d)))
+                .collect()
+        })
+    }
+
+    /// Projects declaring a direct dependency on the given project.
+    pub fn dependents(&self, project: &str) -> Vec<&RepositoryItem> {
+        self.dependents.get(project).map_or_else(Vec::new, |d| {
+            d.iter().filter_map(|p| self.items.get(p)).collect()
+        })
+    }
+
+    /// Every project depending on the given one, directly or transitively, with its
+    /// shortest distance, ordered by depth.
+    pub fn transitive_dependents(&self, project: &str) -> Vec<(&RepositoryItem, usize)> {
+        let mut found = Vec::<(&RepositoryItem, usize)>::new();
+        let mut visited = HashSet::<String>::new();
+        let mut queue = VecDeque::<(String, usize)>::new();
+        visited.insert(project.to_string());
+        queue.push_back((project.to_string(), 0));
+        while let Some((current, depth)) = queue.pop_front() {
+            for dependent in self.dependents(&current) {
+                if visited.insert(dependent.project.to_string()) {
+                    found.push((dependent, depth + 1));
+                    queue.push_back((dependent.project.to_string(), depth + 1));
+                }
+            }
+        }
+        found.sort_by(|(a, a_depth), (b, b_depth)| {
+            a_depth.cmp(b_depth).then_with(|| a.project.cmp(&b.project))
+        });
+        found
+    }
+
+    /// Every dependency path leading from a project to another one, as the edges followed
+    /// at each hop.
+    pub fn paths(&self, from: &str, to: &str) -> Vec<Vec<(&Dependency, &RepositoryItem)>> {
+        let mut paths = Vec::<Vec<(&Dependency, &RepositoryItem)>>::new();
+        let mut current = Vec::<(&Dependency, &RepositoryItem)>::new();
+        self.collect_paths(from, to, &mut current, &mut paths);
+        paths
+    }
+
+    fn collect_paths<'a>(
+        &'a self,
+        project: &str,
+        target: &str,
+        current: &mut Vec<(&'a Dependency, &'a RepositoryItem)>,
+        paths: &mut Vec<Vec<(&'a Dependency, &'a RepositoryItem)>>,
+    ) {
+        for (dep, ri) in self.edges(project) {
+            if current
+                .iter()
+                .any(|(_, visited)| visited.project == ri.project)
+            {
+                continue;
+            }
+            current.push((dep, ri));
+            if ri.project == target {
+                paths.push(current.clone());
+            } else {
+                self.collect_paths(&ri.project, target, current, paths);
+            }
+            current.pop();
+        }
+    }
+
+    /// Returns the first dependency cycle reachable from the project, if any.
+    pub fn find_cycle(&self, project: &str) -> Option<DependencyCycle> {
+        self.topological_order(project).err()
+    }
+
+    /// Projects reachable from the given one, dependencies first and the project itself last.
+    pub fn topological_order(&self, project: &str) -> Result<Vec<String>, DependencyCycle> {
+        self.topological_order_of(&[project.to_string()])
+    }
+
+    /// Projects reachable from any of the given ones, dependencies first.
+    pub fn topological_order_of(
+        &self,
+        projects: &[String],
+    ) -> Result<Vec<String>, DependencyCycle> {
+        let mut order = Vec::<String>::new();
+        let mut visited = HashSet::<String>::new();
+        let mut stack = Vec::<String>::new();
+        for project in projects {
+            self.visit(project, &mut visited, &mut stack, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    /// Changed projects and all their transitive dependents, in topological order.
+    pub fn affected(&self, changed: &[String]) -> Result<Vec<String>, DependencyCycle> {
+        let mut affected: HashSet<String> = changed.iter().cloned().collect();
+        changed.iter().for_each(|project| {
+            self.transitive_dependents(project)
+                .iter()
+                .for_each(|(ri, _)| {
+                    affected.insert(ri.project.to_string());
+                });
+        });
+        let mut seeds: Vec<String> = affected.iter().cloned().collect();
+        seeds.sort();
+        let order = self.topological_order_of(&seeds)?;
+        Ok(order.into_iter().filter(|p| affected.contains(p)).collect())
+    }
+
+    /// Every project reachable from the given one, each listed once in topological order.
+    pub fn plan(&self, project: &str) -> Result<TraversalPlan, DependencyCycle> {
+        let projects = self.topological_order(project)?;
+        let mut paths = HashMap::<String, usize>::new();
+        projects.iter().for_each(|p| {
+            let count = 1 + self
+                .dependencies(p)
+                .iter()
+                .map(|dep| paths[&dep.project])
+                .sum::<usize>();
+            paths.insert(p.to_string(), count);
+        });
+        Ok(TraversalPlan {
+            root: project.to_string(),
+            visits: paths[project],
+            projects,
+        })
+    }
+
+    fn visit(
+        &self,
+        project: &str,
+        visited: &mut HashSet<String>,
+        stack: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<(), DependencyCycle> {
+        if let Some(position) = stack.iter().position(|p| p == project) {
+            let mut path: Vec<String> = stack[position..].to_vec();
+            path.push(project.to_string());
+            return Err(DependencyCycle { path });
+        }
+        if visited.contains(project) {
+            return Ok(());
+        }
+        stack.push(project.to_string());
+        for dep in self.dependencies(project) {
+            self.visit(&dep.project, visited, stack, order)?;
+        }
+        stack.pop();
+        visited.insert(project.to_string());
+        order.push(project.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::structs::DependencyKind;
+
+    /// Graph of projects given as (project, dependencies), each published as `@io/<project>`.
+    fn graph(projects: &[(&str, &[&str])]) -> DependencyGraph {
+        DependencyGraph::new(
+            projects
+                .iter()
+                .map(|(project, dependencies)| {
+                    RepositoryItem::new(
+                        project.to_string(),
+                        format!("@io/{}", project),
+                        "1.0.0".to_string(),
+                        dependencies
+                            .iter()
+                            .map(|d| {
+                                Dependency::new(
+                                    format!("@io/{}", d),
+                                    DependencyKind::Prod,
+                                    "^1.0.0".to_string(),
+                                )
+                            })
+                            .collect(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn diamond() -> DependencyGraph {
+        graph(&[
+            ("app", &["left", "right"]),
+            ("left", &["utils"]),
+            ("right", &["utils"]),
+            ("utils", &[]),
+        ])
+    }
+
+    fn position(order: &[String], project: &str) -> usize {
+        order.iter().position(|p| p == project).unwrap()
+    }
+
+    #[test]
+    fn topological_order_puts_dependencies_first() {
+        let order = diamond().topological_order("app").ok().unwrap();
+        assert_eq!(order.len(), 4);
+        assert_eq!(order[0], "utils");
+        assert_eq!(order[3], "app");
+        assert!(position(&order, "left") < position(&order, "app"));
+        assert!(position(&order, "right") < position(&order, "app"));
+    }
+
+    #[test]
+    fn topological_order_only_follows_reachable_projects() {
+        let order = diamond().topological_order("left").ok().unwrap();
+        assert_eq!(order, vec!["utils", "left"]);
+    }
+
+    #[test]
+    fn topological_order_ignores_external_modules() {
+        let graph = graph(&[("app", &["utils", "react"]), ("utils", &[])]);
+        assert_eq!(
+            graph.topological_order("app").ok().unwrap(),
+            vec!["utils", "app"]
+        );
+    }
+
+    #[test]
+    fn find_cycle_reports_the_cycle_path() {
+        let graph = graph(&[("app", &["lib"]), ("lib", &["utils"]), ("utils", &["lib"])]);
+        let cycle = graph.find_cycle("app").unwrap();
+        assert_eq!(cycle.path, vec!["lib", "utils", "lib"]);
+        assert_eq!(cycle.to_string(), "lib -> utils -> lib");
+    }
+
+    #[test]
+    fn find_cycle_reports_self_dependencies() {
+        let graph = graph(&[("app", &["app"])]);
+        assert_eq!(graph.find_cycle("app").unwrap().path, vec!["app", "app"]);
+    }
+
+    #[test]
+    fn find_cycle_accepts_diamonds() {
+        assert!(diamond().find_cycle("app").is_none());
+    }
+
+    #[test]
+    fn plan_visits_shared_dependencies_once() {
+        let plan = diamond().plan("app").ok().unwrap();
+        assert_eq!(plan.projects.len(), 4);
+        // app, left, right and utils once through each side
+        assert_eq!(plan.visits, 5);
+        assert_eq!(plan.saved_visits(), 1);
+        assert_eq!(plan.dependencies().len(), 3);
+        assert!(!plan.dependencies().contains(&&"app".to_string()));
+    }
+
+    #[test]
+    fn plan_counts_every_path_of_nested_diamonds() {
+        let graph = graph(&[
+            ("app", &["a", "b"]),
+            ("a", &["c", "d"]),
+            ("b", &["c", "d"]),
+            ("c", &["utils"]),
+            ("d", &["utils"]),
+            ("utils", &[]),
+        ]);
+        let plan = graph.plan("app").ok().unwrap();
+        assert_eq!(plan.projects.len(), 6);
+        // utils is reached through 4 paths, c and d through 2 each
+        assert_eq!(plan.visits, 1 + 2 + 4 + 4);
+    }
+
+    #[test]
+    fn plan_fails_on_cycles() {
+        let graph = graph(&[("app", &["lib"]), ("lib", &["app"])]);
+        assert!(graph.plan("app").is_err());
+    }
+
+    #[test]
+    fn plan_of_an_unknown_root_only_holds_the_root() {
+        let plan = diamond().plan("missing").ok().unwrap();
+        assert_eq!(plan.projects, vec!["missing"]);
+        assert_eq!(plan.visits, 1);
+        assert!(plan.dependencies().is_empty());
+    }
+
+    #[test]
+    fn transitive_dependents_have_their_shortest_depth() {
+        let graph = graph(&[
+            ("app", &["lib", "utils"]),
+            ("lib", &["utils"]),
+            ("utils", &[]),
+            ("other", &[]),
+        ]);
+        let dependents: Vec<(&str, usize)> = graph
+            .transitive_dependents("utils")
+            .iter()
+            .map(|(ri, depth)| (ri.project.as_str(), *depth))
+            .collect();
+        assert_eq!(dependents, vec![("app", 1), ("lib", 1)]);
+    }
+
+    #[test]
+    fn transitive_dependents_are_ordered_by_depth() {
+        let graph = graph(&[("app", &["lib"]), ("lib", &["utils"]), ("utils", &[])]);
+        let dependents: Vec<(&str, usize)> = graph
+            .transitive_dependents("utils")
+            .iter()
+            .map(|(ri, depth)| (ri.project.as_str(), *depth))
+            .collect();
+        assert_eq!(dependents, vec![("lib", 1), ("app", 2)]);
+    }
+
+    #[test]
+    fn transitive_dependents_stop_on_cycles() {
+        let graph = graph(&[("lib", &["utils"]), ("utils", &["lib"])]);
+        let dependents = graph.transitive_dependents("utils");
+        assert_eq!(dependents.len(), 1);
+        assert_eq!(dependents[0].0.project, "lib");
+    }
+
+    #[test]
+    fn transitive_dependents_of_an_unknown_project_are_empty() {
+        assert!(diamond().transitive_dependents("missing").is_empty());
+        assert!(diamond().find("missing").is_none());
+    }
+
+    #[test]
+    fn find_resolves_projects_and_modules() {
+        let graph = diamond();
+        assert_eq!(graph.find("left").unwrap().module, "@io/left");
+        assert_eq!(graph.find("@io/left").unwrap().project, "left");
+    }
+}
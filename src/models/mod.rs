@@ -1 +1,2 @@
-pub mod structs;
\ No newline at end of file
+pub mod graph;
+pub mod structs;
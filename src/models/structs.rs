@@ -1,37 +1,525 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::exit;
 use std::str::FromStr;
 use std::string::ParseError;
 use structopt::StructOpt;
 
 fn parse_path(src: &str) -> Result<PathBuf, ParseError> {
-    return PathBuf::from_str(&src)
-        .map(|p| fs::canonicalize(p).expect(""))
-        .map_err(|e| e);
+    PathBuf::from_str(src).map(|p| fs::canonicalize(p).expect(""))
 }
 
 #[derive(StructOpt)]
 pub struct Params {
     pub action: String,
+    #[structopt(default_value = "")]
     pub project: String,
+    /// Module to explain with the why command, project to run the script on with run
     #[structopt(default_value = "")]
-    pub node_version: String,
+    pub target: String,
     #[structopt(parse(try_from_str = parse_path),default_value=".")]
     pub path: PathBuf,
+    /// Node version to switch to with n before running the action
+    #[structopt(long, default_value = "")]
+    pub node_version: String,
+    /// Dependency kinds to follow: prod, dev, peer, optional (defaults to prod)
+    #[structopt(long = "kind", number_of_values = 1)]
+    pub kinds: Vec<DependencyKind>,
+    /// Output format: dot, mermaid or json for graph, json for listings
+    #[structopt(long, default_value = "dot")]
+    pub format: GraphFormat,
+    /// Label graph edges with their dependency kind
+    #[structopt(long)]
+    pub show_kinds: bool,
+    /// Git ref to look for changes since
+    #[structopt(long)]
+    pub since: Option<String>,
+    /// Run scripts on dependents before their dependencies
+    #[structopt(long)]
+    pub reverse: bool,
+    /// Run the exec command in every project at the same time
+    #[structopt(long)]
+    pub parallel: bool,
+    /// Command to execute in every project with the exec command
+    #[structopt(last = true)]
+    pub command: Vec<String>,
+    /// Milliseconds without file changes before the watch command rebuilds
+    #[structopt(long, default_value = "300")]
+    pub debounce: u64,
+    /// Restore the backups of every project of the metarepo
+    #[structopt(long)]
+    pub all: bool,
+    /// Redo the operations of an incomplete journal instead of undoing them
+    #[structopt(long)]
+    pub replay: bool,
+    /// Print the filesystem operations and commands instead of performing them
+    #[structopt(long)]
+    pub dry_run: bool,
+    /// Number of projects to process in parallel
+    #[structopt(short, long, default_value = "1")]
+    pub jobs: usize,
+    /// Build every project even when its inputs did not change
+    #[structopt(long)]
+    pub no_cache: bool,
+    /// Days after which unused build artifacts are pruned from the cache
+    #[structopt(long, default_value = "30")]
+    pub max_age: u64,
+    /// Link or copy packages even when their version does not satisfy the declared range
+    #[structopt(long)]
+    pub force: bool,
+    /// Only operate on projects affected by changes since --since
+    #[structopt(long)]
+    pub affected: bool,
+}
+
+impl Params {
+    pub fn dependency_kinds(&self) -> Vec<DependencyKind> {
+        if self.kinds.is_empty() {
+            return vec![DependencyKind::Prod];
+        }
+        self.kinds.clone()
+    }
+
+    pub fn affected_since(&self) -> Option<&str> {
+        if !self.affected {
+            return None;
+        }
+        match &self.since {
+            Some(since) => Some(since),
+            None => {
+                println!("Option --affected requires --since <ref>!");
+                exit(1);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DependencyKind {
+    Prod,
+    Dev,
+    Peer,
+    Optional,
+}
+
+impl FromStr for DependencyKind {
+    type Err = String;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "prod" | "dependencies" => Ok(DependencyKind::Prod),
+            "dev" | "devDependencies" => Ok(DependencyKind::Dev),
+            "peer" | "peerDependencies" => Ok(DependencyKind::Peer),
+            "optional" | "optionalDependencies" => Ok(DependencyKind::Optional),
+            _ => Err(format!("Unknown dependency kind {}", src)),
+        }
+    }
+}
+
+impl fmt::Display for DependencyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DependencyKind::Prod => "prod",
+            DependencyKind::Dev => "dev",
+            DependencyKind::Peer => "peer",
+            DependencyKind::Optional => "optional",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "dot" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            "json" => Ok(GraphFormat::Json),
+            _ => Err(format!("Unknown graph format {}", src)),
+        }
+    }
+}
+
+pub struct Dependency {
+    pub module: String,
+    pub kind: DependencyKind,
+    pub range: String,
+}
+
+impl Dependency {
+    pub fn new(module: String, kind: DependencyKind, range: String) -> Self {
+        Self {
+            module,
+            kind,
+            range,
+        }
+    }
 }
 
 pub struct RepositoryItem {
     pub project: String,
     pub module: String,
-    pub dependencies: Vec<String>,
+    pub version: String,
+    pub dependencies: Vec<Dependency>,
 }
 
 impl RepositoryItem {
-    pub fn new(project: String, module: String, dependencies: Vec<String>) -> Self {
+    pub fn new(
+        project: String,
+        module: String,
+        version: String,
+        dependencies: Vec<Dependency>,
+    ) -> Self {
         Self {
             project,
             module,
+            version,
             dependencies,
         }
     }
 }
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageJson {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dev_dependencies: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub peer_dependencies: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub optional_dependencies: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scripts: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub types: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typings: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub engines: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspaces: Option<Workspaces>,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Workspaces {
+    Packages(Vec<String>),
+    Config {
+        #[serde(default)]
+        packages: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        nohoist: Vec<String>,
+    },
+}
+
+pub enum PackageJsonError {
+    Missing(String),
+    Invalid(String, String),
+    MissingName(String),
+}
+
+impl fmt::Display for PackageJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackageJsonError::Missing(project) => {
+                write!(f, "Project {} has no package.json file", project)
+            }
+            PackageJsonError::Invalid(project, error) => {
+                write!(
+                    f,
+                    "Project {} has an invalid package.json: {}",
+                    project, error
+                )
+            }
+            PackageJsonError::MissingName(project) => {
+                write!(f, "Project {} has a package.json without name", project)
+            }
+        }
+    }
+}
+
+impl PackageJson {
+    pub fn read(project: &str, project_path: &Path) -> Result<Self, PackageJsonError> {
+        let mut package_path = PathBuf::from(project_path);
+        package_path.push("package.json");
+        let content = fs::read_to_string(package_path)
+            .map_err(|_| PackageJsonError::Missing(project.to_string()))?;
+        let package: PackageJson = serde_json::from_str(&content)
+            .map_err(|e| PackageJsonError::Invalid(project.to_string(), e.to_string()))?;
+        match &package.name {
+            Some(name) if !name.trim().is_empty() => Ok(package),
+            _ => Err(PackageJsonError::MissingName(project.to_string())),
+        }
+    }
+
+    pub fn module(&self) -> String {
+        self.name.clone().unwrap_or_default()
+    }
+
+    pub fn dependencies_of(&self, kind: DependencyKind) -> &BTreeMap<String, String> {
+        match kind {
+            DependencyKind::Prod => &self.dependencies,
+            DependencyKind::Dev => &self.dev_dependencies,
+            DependencyKind::Peer => &self.peer_dependencies,
+            DependencyKind::Optional => &self.optional_dependencies,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct MetaFile {
+    #[serde(default)]
+    pub projects: BTreeMap<String, String>,
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+pub enum MetaFileError {
+    Missing(PathBuf),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for MetaFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetaFileError::Missing(path) => {
+                write!(f, "Can't find .meta file in {}", path.display())
+            }
+            MetaFileError::Invalid(path, error) => {
+                write!(
+                    f,
+                    "File .meta in {} is not valid: {}",
+                    path.display(),
+                    error
+                )
+            }
+        }
+    }
+}
+
+impl MetaFile {
+    pub fn read(meta_path: &Path) -> Result<Self, MetaFileError> {
+        let mut file_path = PathBuf::from(meta_path);
+        file_path.push(".meta");
+        let content = fs::read_to_string(file_path)
+            .map_err(|_| MetaFileError::Missing(meta_path.to_path_buf()))?;
+        serde_json::from_str(&content)
+            .map_err(|e| MetaFileError::Invalid(meta_path.to_path_buf(), e.to_string()))
+    }
+
+    pub fn is_ignored(&self, project: &str) -> bool {
+        let project = project.trim_matches('/');
+        self.ignore.iter().any(|i| {
+            let ignored = i.trim_matches('/');
+            project == ignored || project.starts_with(&format!("{}/", ignored))
+        })
+    }
+}
+
+pub struct MetaProject {
+    pub path: String,
+    pub url: String,
+}
+
+impl MetaProject {
+    pub fn new(path: String, url: String) -> Self {
+        Self { path, url }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct BuildCache {
+    #[serde(default)]
+    pub hashes: BTreeMap<String, String>,
+}
+
+impl BuildCache {
+    pub fn read(cache_path: &Path) -> Self {
+        fs::read_to_string(cache_path.join("hashes.json"))
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn write(&self, cache_path: &Path) {
+        fs::create_dir_all(cache_path).expect("error creating cache directory");
+        let content = serde_json::to_string_pretty(self).expect("error serializing cache");
+        fs::write(cache_path.join("hashes.json"), content).expect("error writing cache");
+    }
+
+    pub fn is_fresh(&self, project: &str, hash: &str) -> bool {
+        self.hashes.get(project).map(|h| h.as_str()) == Some(hash)
+    }
+}
+
+/// Backups of original packages, by dependency path relative to the metarepo root, and
+/// projects in which pack installed tarballs.
+#[derive(Serialize, Deserialize, Default)]
+pub struct RepoState {
+    #[serde(default)]
+    pub backups: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub packed: BTreeSet<String>,
+}
+
+impl RepoState {
+    pub fn read(state_path: &Path) -> Self {
+        fs::read_to_string(state_path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn write(&self, state_path: &Path) {
+        if let Some(parent) = state_path.parent() {
+            fs::create_dir_all(parent).expect("error creating state directory");
+        }
+        let content = serde_json::to_string_pretty(self).expect("error serializing state");
+        fs::write(state_path, content).expect("error writing state");
+    }
+}
+
+/// Filesystem operation of a link, unlink or copy run, recorded before it is performed.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "operation", rename_all = "camelCase")]
+pub enum JournalOperation {
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Symlink {
+        target: PathBuf,
+        link: PathBuf,
+    },
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    RemoveLink {
+        target: PathBuf,
+        link: PathBuf,
+    },
+    RemoveCopy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Install {
+        project: PathBuf,
+        archives: Vec<PathBuf>,
+        paths: Vec<PathBuf>,
+    },
+}
+
+impl fmt::Display for JournalOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JournalOperation::Rename { from, to } => {
+                write!(f, "mv {} {}", from.display(), to.display())
+            }
+            JournalOperation::Symlink { target, link } => {
+                write!(f, "ln -s {} {}", target.display(), link.display())
+            }
+            JournalOperation::Copy { from, to } => {
+                write!(f, "copy {} {}", from.display(), to.display())
+            }
+            JournalOperation::RemoveLink { link, .. } => write!(f, "rm {}", link.display()),
+            JournalOperation::RemoveCopy { to, .. } => write!(f, "rm -rf {}", to.display()),
+            JournalOperation::Install {
+                project, archives, ..
+            } => {
+                let files: Vec<String> = archives
+                    .iter()
+                    .map(|a| format!("file:{}", a.display()))
+                    .collect();
+                write!(
+                    f,
+                    "yarn --cwd {} add {}",
+                    project.display(),
+                    files.join(" ")
+                )
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Journal {
+    pub action: String,
+    pub project: String,
+    #[serde(default)]
+    pub operations: Vec<JournalOperation>,
+}
+
+impl Journal {
+    pub fn new(action: &str, project: &str) -> Self {
+        Self {
+            action: action.to_string(),
+            project: project.to_string(),
+            operations: Vec::new(),
+        }
+    }
+
+    pub fn read(journal_path: &Path) -> Option<Self> {
+        fs::read_to_string(journal_path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+    }
+
+    pub fn write(&self, journal_path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = journal_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string_pretty(self).expect("error serializing journal");
+        fs::write(journal_path, content)
+    }
+}
+
+/// State of an internal dependency in a project's node_modules.
+pub enum LinkState {
+    /// Symlinked to the given local project.
+    Linked(PathBuf),
+    /// Copied from the local project, with the number of files drifted from the source.
+    Copied(usize),
+    /// Installed from the registry.
+    Original,
+    /// A symlink whose target does not exist.
+    Broken(String),
+    /// Not installed.
+    Missing,
+}
+
+impl fmt::Display for LinkState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkState::Linked(target) => write!(f, "linked -> {}", target.display()),
+            LinkState::Copied(0) => write!(f, "copied, up to date"),
+            LinkState::Copied(drift) => write!(f, "copied, {} files drifted", drift),
+            LinkState::Original => write!(f, "original"),
+            LinkState::Broken(reason) => write!(f, "broken, {}", reason),
+            LinkState::Missing => write!(f, "broken, not installed"),
+        }
+    }
+}
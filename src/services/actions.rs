@@ -1,56 +1,225 @@
+use crate::models::structs::Params;
+use crate::services::cache::{print_cache_stats, prune_cache};
+use crate::services::journal;
 use crate::traits::metarepo_traits::MetarepoExtension;
-use std::path::PathBuf;
 
-pub fn link(project: &String, root_path: &PathBuf) {
+pub fn link(args: &Params) {
     println!("\n-------------------------------------------------");
     println!("Linking modules...");
-    println!("Path: {}", root_path.display().to_string());
-    println!("Project: {}", project);
+    println!("Path: {}", args.path.display());
+    println!("Project: {}", args.project);
     println!("-------------------------------------------------\n");
-    root_path.package_to_link(project);
+    args.path
+        .package_to_link(&args.project, &args.dependency_kinds(), args.force);
 }
 
-pub fn unlink(project: &String, root_path: &PathBuf) {
+pub fn unlink(args: &Params) {
     println!("\n-------------------------------------------------");
     println!("Unlinking modules...");
-    println!("Path: {}", root_path.display().to_string());
-    println!("Project: {}", project);
+    println!("Path: {}", args.path.display());
+    println!("Project: {}", args.project);
     println!("-------------------------------------------------\n");
-    root_path.link_to_package(project);
+    args.path
+        .link_to_package(&args.project, &args.dependency_kinds());
 }
 
-pub fn copy(project: &String, root_path: &PathBuf) {
+pub fn copy(args: &Params) {
     println!("\n-------------------------------------------------");
     println!("Copying modules...");
-    println!("Path: {}", root_path.display().to_string());
-    println!("Project: {}", project);
+    println!("Path: {}", args.path.display());
+    println!("Project: {}", args.project);
+    println!("-------------------------------------------------\n");
+    args.path
+        .copy_packages(&args.project, &args.dependency_kinds(), args.force);
+}
+
+pub fn pack(args: &Params) {
+    println!("\n-------------------------------------------------");
+    println!("Packing and installing modules...");
+    println!("Path: {}", args.path.display());
+    println!("Project: {}", args.project);
+    println!("-------------------------------------------------\n");
+    args.path
+        .pack_packages(&args.project, &args.dependency_kinds(), args.force);
+}
+
+pub fn unpack(args: &Params) {
+    println!("\n-------------------------------------------------");
+    println!("Unpacking modules...");
+    println!("Path: {}", args.path.display());
+    println!("Project: {}", args.project);
+    println!("-------------------------------------------------\n");
+    args.path
+        .unpack_packages(&args.project, &args.dependency_kinds());
+}
+
+pub fn sync(args: &Params) {
+    println!("\n-------------------------------------------------");
+    println!("Copying and syncing modules...");
+    println!("Path: {}", args.path.display());
+    println!("Project: {}", args.project);
     println!("-------------------------------------------------\n");
-    root_path.copy_packages(project);
+    args.path.sync_packages(
+        &args.project,
+        &args.dependency_kinds(),
+        args.force,
+        args.debounce,
+    );
 }
 
-pub fn update(project: &String, root_path: &PathBuf) {
+pub fn restore(args: &Params) {
+    if args.project.is_empty() && !args.all {
+        println!("Command restore requires a project or --all!");
+        return;
+    }
+    println!("\n-------------------------------------------------");
+    println!("Restoring backups...");
+    println!("Path: {}", args.path.display());
+    println!(
+        "Project: {}",
+        if args.all { "--all" } else { &args.project }
+    );
+    println!("-------------------------------------------------\n");
+    args.path
+        .restore_backups(&args.project, &args.dependency_kinds(), args.all);
+}
+
+pub fn recover(args: &Params) {
+    println!("\n-------------------------------------------------");
+    println!("Recovering incomplete operations...");
+    println!("Path: {}", args.path.display());
+    println!("-------------------------------------------------\n");
+    journal::recover(&args.path, args.replay);
+}
+
+pub fn update(args: &Params) {
     println!("\n-------------------------------------------------");
     println!("Updating repository to latest minor release...");
-    println!("Path: {}", root_path.display().to_string());
-    println!("Project: {}", project);
+    println!("Path: {}", args.path.display());
+    println!("Project: {}", args.project);
     println!("-------------------------------------------------\n");
-    root_path.update_dependencies(project);
+    args.path
+        .update_dependencies(&args.project, &args.dependency_kinds());
 }
 
-pub fn build(project: &String, root_path: &PathBuf) {
+pub fn build(args: &Params) {
     println!("\n-------------------------------------------------");
     println!("Building project tree...");
-    println!("Path: {}", root_path.display().to_string());
+    println!("Path: {}", args.path.display());
+    println!("Project: {}", args.project);
+    println!("-------------------------------------------------\n");
+    args.path.build_tree(
+        &args.project,
+        &args.dependency_kinds(),
+        args.affected_since(),
+        args.jobs,
+        args.no_cache,
+    );
+}
+
+pub fn test(args: &Params) {
+    run_tree(args, "test", &args.project);
+}
+
+pub fn run(args: &Params) {
+    if args.project.is_empty() {
+        println!("Command run requires a script!");
+        return;
+    }
+    run_tree(args, &args.project, &args.target);
+}
+
+fn run_tree(args: &Params, script: &str, project: &str) {
+    println!("\n-------------------------------------------------");
+    println!("Running {} on project tree...", script);
+    println!("Path: {}", args.path.display());
     println!("Project: {}", project);
     println!("-------------------------------------------------\n");
-    root_path.build_tree(project);
+    args.path.run_script(
+        project,
+        script,
+        &args.dependency_kinds(),
+        args.affected_since(),
+        args.reverse,
+        args.jobs,
+    );
 }
 
-pub fn clean(project: &String, root_path: &PathBuf) {
+pub fn exec(args: &Params) {
+    if args.command.is_empty() {
+        println!("Command exec requires a command after --!");
+        return;
+    }
+    args.path.exec_command(
+        &args.project,
+        &args.command,
+        &args.dependency_kinds(),
+        args.parallel,
+        args.jobs,
+    );
+}
+
+pub fn watch(args: &Params) {
+    println!("\n-------------------------------------------------");
+    println!("Watching project dependencies...");
+    println!("Path: {}", args.path.display());
+    println!("Project: {}", args.project);
+    println!("-------------------------------------------------\n");
+    args.path
+        .watch_tree(&args.project, &args.dependency_kinds(), args.debounce);
+}
+
+pub fn clean(args: &Params) {
     println!("\n-------------------------------------------------");
     println!("Cleaning project tree...");
-    println!("Path: {}", root_path.display().to_string());
-    println!("Project: {}", project);
+    println!("Path: {}", args.path.display());
+    println!("Project: {}", args.project);
     println!("-------------------------------------------------\n");
-    root_path.clean_tree(project);
+    args.path
+        .clean_tree(&args.project, &args.dependency_kinds());
+}
+
+pub fn status(args: &Params) {
+    args.path
+        .print_status(&args.project, &args.dependency_kinds());
+}
+
+pub fn graph(args: &Params) {
+    args.path.print_graph(
+        &args.project,
+        &args.dependency_kinds(),
+        args.format,
+        args.show_kinds,
+    );
+}
+
+pub fn dependents(args: &Params) {
+    args.path
+        .print_dependents(&args.project, &args.dependency_kinds(), args.format);
+}
+
+pub fn affected(args: &Params) {
+    match &args.since {
+        Some(since) => args
+            .path
+            .print_affected(since, &args.dependency_kinds(), args.format),
+        None => println!("Command affected requires --since <ref>!"),
+    }
+}
+
+pub fn why(args: &Params) {
+    if args.project.is_empty() || args.target.is_empty() {
+        println!("Command why requires a project and a module!");
+        return;
+    }
+    args.path
+        .print_why(&args.project, &args.target, &args.dependency_kinds());
+}
+
+pub fn cache(args: &Params) {
+    match &args.project[..] {
+        "stats" => print_cache_stats(&args.path),
+        "prune" => prune_cache(&args.path, args.max_age),
+        _ => println!("Cache command not found, use stats or prune!"),
+    }
 }
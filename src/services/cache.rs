@@ -0,0 +1,393 @@
+use crate::models::structs::BuildCache;
+use crate::services::git::{git_list_files, git_tracked_files};
+use crate::traits::vec_traits::VecExtension;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::slice;
+use std::time::{Duration, SystemTime};
+
+const IGNORED_FOLDERS: [&str; 3] = ["node_modules", ".git", ".repo"];
+const ALWAYS_HASHED: [&str; 2] = ["package.json", "yarn.lock"];
+
+/// Source files of a project relative to its path: the files git would track, or every
+/// file outside dependency and output folders when the project is not a git repository.
+/// Build outputs are left out either way, so building does not change the hash.
+pub fn source_files(project_path: &Path) -> Vec<String> {
+    let outputs = output_paths(project_path);
+    let mut files = git_list_files(&project_path.display().to_string()).unwrap_or_else(|| {
+        let mut files = Vec::<String>::new();
+        walk_files(project_path, project_path, &IGNORED_FOLDERS, &mut files);
+        files
+    });
+    files.retain(|file| !is_output(file, &outputs));
+    ALWAYS_HASHED.iter().for_each(|f| {
+        if project_path.join(f).is_file() && !files.iter().any(|s| s == f) {
+            files.push(f.to_string());
+        }
+    });
+    files.sort();
+    files.dedup();
+    files
+}
+
+fn is_output(file: &str, outputs: &[String]) -> bool {
+    outputs
+        .iter()
+        .any(|o| file == o || file.starts_with(&format!("{}/", o)))
+}
+
+/// Collects the files under `dir` relative to `root`, without descending into folders
+/// named like one of `skipped`.
+pub fn walk_files(root: &Path, dir: &Path, skipped: &[&str], files: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    entries.flatten().for_each(|entry| {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if path.is_dir() {
+            if !skipped.contains(&name.as_str()) {
+                walk_files(root, &path, skipped, files);
+            }
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.display().to_string());
+        }
+    });
+}
+
+/// Hash of a project's sources, package.json, lockfile and of the hashes of its
+/// internal dependencies.
+pub fn hash_project(project_path: &Path, dependency_hashes: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    source_files(project_path).iter().for_each(|file| {
+        hasher.update(file.as_bytes());
+        hasher.update([0]);
+        if let Ok(content) = fs::read(project_path.join(file)) {
+            hasher.update(&content);
+        }
+        hasher.update([0]);
+    });
+    dependency_hashes
+        .iter()
+        .for_each(|h| hasher.update(h.as_bytes()));
+    format!("{:x}", hasher.finalize())
+}
+
+pub fn cache_path(root_path: &Path) -> PathBuf {
+    let mut path = PathBuf::from(root_path);
+    path.push(".repo");
+    path.push("cache");
+    path
+}
+
+const DEFAULT_OUTPUTS: [&str; 3] = ["dist", "lib", "build"];
+
+fn artifacts_path(root_path: &Path) -> PathBuf {
+    let mut path = cache_path(root_path);
+    path.push("artifacts");
+    path
+}
+
+fn artifact_path(root_path: &Path, hash: &str) -> PathBuf {
+    artifacts_path(root_path).join(format!("{}.tar.gz", hash))
+}
+
+/// Build outputs of a project: the usual output folders, except for the ones holding
+/// files tracked by git, which are sources and must never be archived or replaced.
+pub fn output_paths(project_path: &Path) -> Vec<String> {
+    let tracked = git_tracked_files(&project_path.display().to_string()).unwrap_or_default();
+    DEFAULT_OUTPUTS
+        .iter()
+        .map(|o| o.to_string())
+        .filter(|o| {
+            !tracked
+                .iter()
+                .any(|file| is_output(file, slice::from_ref(o)))
+        })
+        .collect()
+}
+
+/// Top-level entries of the archive stored under a hash, if there is one.
+fn archived_outputs(root_path: &Path, hash: &str) -> Option<Vec<String>> {
+    let output = Command::new("tar")
+        .arg("-tzf")
+        .arg(artifact_path(root_path, hash))
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let mut entries: Vec<String> = output
+        .stdout
+        .get_string()
+        .lines()
+        .filter_map(|entry| entry.trim_start_matches("./").split('/').next())
+        .filter(|entry| !entry.is_empty())
+        .map(String::from)
+        .collect();
+    entries.sort();
+    entries.dedup();
+    Some(entries)
+}
+
+/// Whether the outputs archived under a hash are all still in the project. Projects
+/// whose build stored no archive have nothing to check.
+pub fn has_outputs(root_path: &Path, project_path: &Path, hash: &str) -> bool {
+    if !artifact_path(root_path, hash).is_file() {
+        return true;
+    }
+    archived_outputs(root_path, hash)
+        .map(|entries| entries.iter().all(|e| project_path.join(e).exists()))
+        .unwrap_or(false)
+}
+
+/// Archives the existing outputs of a project under its input hash.
+pub fn store_artifacts(root_path: &Path, project_path: &Path, hash: &str, outputs: &[String]) {
+    let existing: Vec<&String> = outputs
+        .iter()
+        .filter(|o| project_path.join(o).exists())
+        .collect();
+    if existing.is_empty() {
+        return;
+    }
+    fs::create_dir_all(artifacts_path(root_path)).expect("error creating artifacts directory");
+    let output = Command::new("tar")
+        .arg("-czf")
+        .arg(artifact_path(root_path, hash))
+        .arg("-C")
+        .arg(project_path)
+        .args(existing)
+        .output()
+        .expect("Tar error!");
+    if !output.status.success() {
+        output.stderr.log();
+    }
+}
+
+/// Replaces the outputs of a project with the archived ones, if the hash is stored.
+/// Archives holding anything but the current outputs are left alone, so restoring never
+/// overwrites sources.
+pub fn restore_artifacts(
+    root_path: &Path,
+    project_path: &Path,
+    hash: &str,
+    outputs: &[String],
+) -> bool {
+    let archive = artifact_path(root_path, hash);
+    if !archive.is_file() {
+        return false;
+    }
+    let entries = match archived_outputs(root_path, hash) {
+        Some(entries) if entries.iter().all(|e| outputs.contains(e)) => entries,
+        _ => return false,
+    };
+    entries.iter().for_each(|o| {
+        let path = project_path.join(o);
+        if path.is_dir() {
+            fs::remove_dir_all(&path).expect("error removing build output");
+        } else if path.is_file() {
+            fs::remove_file(&path).expect("error removing build output");
+        }
+    });
+    let output = Command::new("tar")
+        .arg("-xzf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(project_path)
+        .output()
+        .expect("Tar error!");
+    if !output.status.success() {
+        output.stderr.log();
+        return false;
+    }
+    // keep recently restored artifacts out of prune
+    Command::new("touch").arg(&archive).output().ok();
+    true
+}
+
+fn list_artifacts(root_path: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    fs::read_dir(artifacts_path(root_path))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| {
+                    let metadata = e.metadata().ok()?;
+                    Some((e.path(), metadata.len(), metadata.modified().ok()?))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn print_cache_stats(root_path: &Path) {
+    let cache = BuildCache::read(&cache_path(root_path));
+    let artifacts = list_artifacts(root_path);
+    let size: u64 = artifacts.iter().map(|(_, size, _)| size).sum();
+    let age_days = |time: &SystemTime| {
+        SystemTime::now()
+            .duration_since(*time)
+            .map(|d| d.as_secs() / 86400)
+            .unwrap_or(0)
+    };
+    println!("Cache directory: {}", cache_path(root_path).display());
+    println!("Tracked projects: {}", cache.hashes.len());
+    println!("Stored artifacts: {}", artifacts.len());
+    println!("Total size: {:.1} MB", size as f64 / 1_048_576.0);
+    if let Some(oldest) = artifacts.iter().map(|(_, _, t)| t).min() {
+        println!("Oldest artifact: {} days", age_days(oldest));
+    }
+}
+
+/// Removes the artifacts not stored or restored in the last `max_age_days` days.
+pub fn prune_cache(root_path: &Path, max_age_days: u64) {
+    let max_age = Duration::from_secs(max_age_days * 86400);
+    let mut removed = 0;
+    let mut freed = 0;
+    list_artifacts(root_path)
+        .iter()
+        .filter(|(_, _, modified)| {
+            SystemTime::now()
+                .duration_since(*modified)
+                .map(|age| age > max_age)
+                .unwrap_or(false)
+        })
+        .for_each(|(path, size, _)| {
+            if fs::remove_file(path).is_ok() {
+                removed += 1;
+                freed += size;
+            }
+        });
+    println!(
+        "Removed {} artifacts older than {} days, {:.1} MB freed.",
+        removed,
+        max_age_days,
+        freed as f64 / 1_048_576.0
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Git project with tracked sources in `src` and `lib`, built into `dist`.
+    struct Fixture {
+        root: PathBuf,
+        project: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("repo-cache-{}-{}", name, std::process::id()));
+            fs::remove_dir_all(&root).ok();
+            let fixture = Self {
+                project: root.join("app"),
+                root,
+            };
+            fixture.write(
+                "package.json",
+                r#"{"name": "app", "files": ["src", "dist"]}"#,
+            );
+            fixture.write("src/a.ts", "v1");
+            fixture.write("lib/b.ts", "v1");
+            fixture.write("dist/a.js", "v1");
+            Command::new("git")
+                .arg("init")
+                .arg("-q")
+                .arg(&fixture.project)
+                .output()
+                .unwrap();
+            Command::new("git")
+                .args(["add", "package.json", "src", "lib"])
+                .current_dir(&fixture.project)
+                .output()
+                .unwrap();
+            fixture
+        }
+
+        fn write(&self, file: &str, content: &str) {
+            let path = self.project.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        fn read(&self, file: &str) -> String {
+            fs::read_to_string(self.project.join(file)).unwrap()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    #[test]
+    fn output_paths_skip_folders_tracked_by_git() {
+        let fixture = Fixture::new("outputs");
+        assert_eq!(output_paths(&fixture.project), vec!["dist", "build"]);
+    }
+
+    #[test]
+    fn source_files_keep_sources_listed_in_files() {
+        let fixture = Fixture::new("sources-hashed");
+        let files = source_files(&fixture.project);
+        assert!(files.contains(&"src/a.ts".to_string()));
+        assert!(!files.iter().any(|f| f.starts_with("dist/")));
+        let hash = hash_project(&fixture.project, &[]);
+        fixture.write("src/a.ts", "v2");
+        assert_ne!(hash_project(&fixture.project, &[]), hash);
+    }
+
+    #[test]
+    fn missing_outputs_are_detected() {
+        let fixture = Fixture::new("missing");
+        store_artifacts(
+            &fixture.root,
+            &fixture.project,
+            "hash",
+            &output_paths(&fixture.project),
+        );
+        assert!(has_outputs(&fixture.root, &fixture.project, "hash"));
+        fs::remove_dir_all(fixture.project.join("dist")).unwrap();
+        assert!(!has_outputs(&fixture.root, &fixture.project, "hash"));
+        assert!(has_outputs(&fixture.root, &fixture.project, "unknown"));
+    }
+
+    #[test]
+    fn restore_replaces_outputs_and_keeps_sources() {
+        let fixture = Fixture::new("restore");
+        let outputs = output_paths(&fixture.project);
+        store_artifacts(&fixture.root, &fixture.project, "hash", &outputs);
+        fixture.write("src/a.ts", "v2");
+        fixture.write("lib/b.ts", "v2");
+        fixture.write("dist/a.js", "v2");
+        assert!(restore_artifacts(
+            &fixture.root,
+            &fixture.project,
+            "hash",
+            &outputs
+        ));
+        assert_eq!(fixture.read("dist/a.js"), "v1");
+        assert_eq!(fixture.read("src/a.ts"), "v2");
+        assert_eq!(fixture.read("lib/b.ts"), "v2");
+    }
+
+    #[test]
+    fn restore_refuses_archives_holding_sources() {
+        let fixture = Fixture::new("sources");
+        let outputs = vec!["src".to_string(), "dist".to_string()];
+        store_artifacts(&fixture.root, &fixture.project, "hash", &outputs);
+        fixture.write("src/a.ts", "v2");
+        assert!(!restore_artifacts(
+            &fixture.root,
+            &fixture.project,
+            "hash",
+            &output_paths(&fixture.project)
+        ));
+        assert_eq!(fixture.read("src/a.ts"), "v2");
+    }
+}
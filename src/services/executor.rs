@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct Task {
+    pub name: String,
+    pub prerequisites: Vec<String>,
+    pub run: Box<dyn FnOnce() -> (bool, String) + Send>,
+}
+
+impl Task {
+    pub fn new<F>(name: String, prerequisites: Vec<String>, run: F) -> Self
+    where
+        F: FnOnce() -> (bool, String) + Send + 'static,
+    {
+        Self {
+            name,
+            prerequisites,
+            run: Box::new(run),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TaskStatus {
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+pub struct TaskReport {
+    pub name: String,
+    pub status: TaskStatus,
+    pub duration: Duration,
+}
+
+/// Runs the tasks on a pool of `jobs` threads, starting each one only after all its
+/// prerequisites succeeded. Tasks depending on a failed one are skipped. Output of each
+/// task is printed prefixed by its name once it completes.
+pub fn execute(tasks: Vec<Task>, jobs: usize) -> Vec<TaskReport> {
+    let order: Vec<String> = tasks.iter().map(|t| t.name.to_string()).collect();
+    let mut pending: Vec<Task> = tasks;
+    let mut statuses = HashMap::<String, TaskStatus>::new();
+    let mut durations = HashMap::<String, Duration>::new();
+    let (sender, receiver) = mpsc::channel::<(String, bool, String, Duration)>();
+    let mut running = 0;
+
+    while !pending.is_empty() || running > 0 {
+        // skip tasks whose prerequisites did not succeed
+        loop {
+            let blocked = pending.iter().position(|t| {
+                t.prerequisites.iter().any(|p| {
+                    matches!(
+                        statuses.get(p),
+                        Some(TaskStatus::Failed) | Some(TaskStatus::Skipped)
+                    )
+                })
+            });
+            match blocked {
+                Some(index) => {
+                    let task = pending.remove(index);
+                    println!("[{}] skipped: a dependency failed", task.name);
+                    statuses.insert(task.name, TaskStatus::Skipped);
+                }
+                None => break,
+            }
+        }
+
+        // start every ready task while workers are available
+        while running < jobs.max(1) {
+            let ready = pending.iter().position(|t| {
+                t.prerequisites
+                    .iter()
+                    .all(|p| statuses.get(p) == Some(&TaskStatus::Succeeded))
+            });
+            let task = match ready {
+                Some(index) => pending.remove(index),
+                None => break,
+            };
+            println!("[{}] started", task.name);
+            let sender = sender.clone();
+            running += 1;
+            thread::spawn(move || {
+                let start = Instant::now();
+                let run = task.run;
+                let (success, output) = panic::catch_unwind(AssertUnwindSafe(run))
+                    .unwrap_or_else(|_| (false, "task panicked".to_string()));
+                sender
+                    .send((task.name, success, output, start.elapsed()))
+                    .expect("error reporting task result");
+            });
+        }
+
+        if running == 0 {
+            // prerequisites that are not part of the run can never be satisfied
+            pending.drain(..).for_each(|task| {
+                println!("[{}] skipped: unresolved dependencies", task.name);
+                statuses.insert(task.name, TaskStatus::Skipped);
+            });
+            break;
+        }
+
+        let (name, success, output, duration) =
+            receiver.recv().expect("error receiving task result");
+        running -= 1;
+        output
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .for_each(|l| println!("[{}] {}", name, l));
+        let status = if success {
+            println!("[{}] done in {:.1}s", name, duration.as_secs_f64());
+            TaskStatus::Succeeded
+        } else {
+            println!("[{}] failed after {:.1}s", name, duration.as_secs_f64());
+            TaskStatus::Failed
+        };
+        statuses.insert(name.to_string(), status);
+        durations.insert(name, duration);
+    }
+
+    order
+        .into_iter()
+        .map(|name| TaskReport {
+            status: statuses[&name],
+            duration: durations.get(&name).copied().unwrap_or_default(),
+            name,
+        })
+        .collect()
+}
+
+pub fn print_reports(reports: &[TaskReport]) {
+    let width = reports
+        .iter()
+        .map(|r| r.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("Project".len());
+    println!();
+    println!("{:width$}  {:9}  Time", "Project", "Status", width = width);
+    println!("{}", "-".repeat(width + 19));
+    reports.iter().for_each(|r| {
+        let (status, time) = match r.status {
+            TaskStatus::Succeeded => ("ok", format!("{:.1}s", r.duration.as_secs_f64())),
+            TaskStatus::Failed => ("failed", format!("{:.1}s", r.duration.as_secs_f64())),
+            TaskStatus::Skipped => ("skipped", "-".to_string()),
+        };
+        println!("{:width$}  {:9}  {}", r.name, status, time, width = width);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Barrier, Mutex};
+
+    /// Task recording its name in `log` once it runs, succeeding unless told otherwise.
+    fn task(name: &str, prerequisites: &[&str], log: &Arc<Mutex<Vec<String>>>, ok: bool) -> Task {
+        let log = Arc::clone(log);
+        let logged = name.to_string();
+        Task::new(
+            name.to_string(),
+            prerequisites.iter().map(|p| p.to_string()).collect(),
+            move || {
+                log.lock().unwrap().push(logged);
+                (ok, String::new())
+            },
+        )
+    }
+
+    fn statuses(reports: &[TaskReport]) -> Vec<(&str, &str)> {
+        reports
+            .iter()
+            .map(|r| {
+                let status = match r.status {
+                    TaskStatus::Succeeded => "ok",
+                    TaskStatus::Failed => "failed",
+                    TaskStatus::Skipped => "skipped",
+                };
+                (r.name.as_str(), status)
+            })
+            .collect()
+    }
+
+    fn position(log: &Arc<Mutex<Vec<String>>>, name: &str) -> usize {
+        log.lock().unwrap().iter().position(|n| n == name).unwrap()
+    }
+
+    #[test]
+    fn prerequisites_run_first() {
+        for jobs in [1, 4] {
+            let log = Arc::new(Mutex::new(Vec::new()));
+            let reports = execute(
+                vec![
+                    task("app", &["left", "right"], &log, true),
+                    task("left", &["utils"], &log, true),
+                    task("right", &["utils"], &log, true),
+                    task("utils", &[], &log, true),
+                ],
+                jobs,
+            );
+            assert!(reports.iter().all(|r| r.status == TaskStatus::Succeeded));
+            assert_eq!(position(&log, "utils"), 0);
+            assert_eq!(position(&log, "app"), 3);
+        }
+    }
+
+    #[test]
+    fn reports_keep_the_order_of_the_tasks() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let reports = execute(
+            vec![
+                task("app", &["utils"], &log, true),
+                task("utils", &[], &log, true),
+            ],
+            1,
+        );
+        assert_eq!(statuses(&reports), vec![("app", "ok"), ("utils", "ok")]);
+    }
+
+    #[test]
+    fn dependents_of_failed_tasks_are_skipped() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let reports = execute(
+            vec![
+                task("utils", &[], &log, false),
+                task("lib", &["utils"], &log, true),
+                task("app", &["lib"], &log, true),
+                task("other", &[], &log, true),
+            ],
+            2,
+        );
+        assert_eq!(
+            statuses(&reports),
+            vec![
+                ("utils", "failed"),
+                ("lib", "skipped"),
+                ("app", "skipped"),
+                ("other", "ok"),
+            ]
+        );
+        assert_eq!(log.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn panicking_tasks_fail() {
+        let reports = execute(
+            vec![Task::new("app".to_string(), vec![], || panic!("boom"))],
+            1,
+        );
+        assert_eq!(statuses(&reports), vec![("app", "failed")]);
+    }
+
+    #[test]
+    fn unresolved_prerequisites_are_skipped() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let reports = execute(
+            vec![
+                task("app", &["missing"], &log, true),
+                task("lib", &["app"], &log, true),
+                task("utils", &[], &log, true),
+            ],
+            1,
+        );
+        assert_eq!(
+            statuses(&reports),
+            vec![("app", "skipped"), ("lib", "skipped"), ("utils", "ok")]
+        );
+    }
+
+    #[test]
+    fn independent_tasks_run_in_parallel() {
+        // each task waits for the other one, so both must run at the same time
+        let barrier = Arc::new(Barrier::new(2));
+        let tasks = ["left", "right"]
+            .iter()
+            .map(|name| {
+                let barrier = Arc::clone(&barrier);
+                Task::new(name.to_string(), vec![], move || {
+                    barrier.wait();
+                    (true, String::new())
+                })
+            })
+            .collect();
+        let reports = execute(tasks, 2);
+        assert_eq!(statuses(&reports), vec![("left", "ok"), ("right", "ok")]);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::models::graph::DependencyGraph;
+use crate::models::structs::{GraphFormat, RepositoryItem};
+use serde_json::json;
+
+pub fn export_graph(
+    graph: &DependencyGraph,
+    projects: &[&RepositoryItem],
+    format: GraphFormat,
+    show_kinds: bool,
+) -> String {
+    match format {
+        GraphFormat::Dot => to_dot(graph, projects, show_kinds),
+        GraphFormat::Mermaid => to_mermaid(graph, projects, show_kinds),
+        GraphFormat::Json => to_json(graph, projects, show_kinds),
+    }
+}
+
+fn to_dot(graph: &DependencyGraph, projects: &[&RepositoryItem], show_kinds: bool) -> String {
+    let mut lines = vec!["digraph dependencies {".to_string()];
+    lines.push("    rankdir=LR;".to_string());
+    projects.iter().for_each(|ri| {
+        lines.push(format!(
+            "    \"{}\" [label=\"{}\"];",
+            ri.project.escape_default(),
+            ri.module.escape_default()
+        ));
+    });
+    projects.iter().for_each(|ri| {
+        graph.edges(&ri.project).iter().for_each(|(dep, target)| {
+            let label = if show_kinds {
+                format!(" [label=\"{}\"]", dep.kind)
+            } else {
+                String::new()
+            };
+            lines.push(format!(
+                "    \"{}\" -> \"{}\"{};",
+                ri.project.escape_default(),
+                target.project.escape_default(),
+                label
+            ));
+        });
+    });
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+fn to_mermaid(graph: &DependencyGraph, projects: &[&RepositoryItem], show_kinds: bool) -> String {
+    let node_id = |project: &str| {
+        projects
+            .iter()
+            .position(|ri| ri.project == project)
+            .map_or_else(String::new, |i| format!("n{}", i))
+    };
+    let mut lines = vec!["graph LR".to_string()];
+    projects.iter().for_each(|ri| {
+        lines.push(format!(
+            "    {}[\"{}\"]",
+            node_id(&ri.project),
+            ri.module.replace('"', "#quot;")
+        ));
+    });
+    projects.iter().for_each(|ri| {
+        graph.edges(&ri.project).iter().for_each(|(dep, target)| {
+            let arrow = if show_kinds {
+                format!("-->|{}|", dep.kind)
+            } else {
+                "-->".to_string()
+            };
+            lines.push(format!(
+                "    {} {} {}",
+                node_id(&ri.project),
+                arrow,
+                node_id(&target.project)
+            ));
+        });
+    });
+    lines.join("\n")
+}
+
+fn to_json(graph: &DependencyGraph, projects: &[&RepositoryItem], show_kinds: bool) -> String {
+    let nodes: Vec<serde_json::Value> = projects
+        .iter()
+        .map(|ri| json!({ "project": ri.project, "module": ri.module }))
+        .collect();
+    let edges: Vec<serde_json::Value> = projects
+        .iter()
+        .flat_map(|ri| {
+            graph
+                .edges(&ri.project)
+                .into_iter()
+                .map(move |(dep, target)| {
+                    let mut edge = json!({ "from": ri.project, "to": target.project });
+                    if show_kinds {
+                        edge["kind"] = json!(dep.kind.to_string());
+                    }
+                    edge
+                })
+        })
+        .collect();
+    serde_json::to_string_pretty(&json!({ "nodes": nodes, "edges": edges }))
+        .expect("error serializing graph")
+}
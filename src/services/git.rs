@@ -0,0 +1,74 @@
+use crate::traits::vec_traits::*;
+use std::process::Command;
+
+pub fn git_changed_files(path: &String, since: &String) -> Option<Vec<String>> {
+    let diff = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .arg("diff")
+        .arg("--name-only")
+        .arg(since)
+        .arg("--")
+        .arg(".")
+        .output()
+        .expect("Git error!");
+    if !diff.status.success() {
+        diff.stderr.log();
+        return None;
+    }
+    let untracked = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .arg("ls-files")
+        .arg("--others")
+        .arg("--exclude-standard")
+        .output()
+        .expect("Git error!");
+    let mut files: Vec<String> = diff.stdout.get_string().lines().map(String::from).collect();
+    files.extend(untracked.stdout.get_string().lines().map(String::from));
+    Some(files)
+}
+
+pub fn git_list_files(path: &String) -> Option<Vec<String>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .arg("ls-files")
+        .arg("--cached")
+        .arg("--others")
+        .arg("--exclude-standard")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(
+        output
+            .stdout
+            .get_string()
+            .lines()
+            .map(String::from)
+            .collect(),
+    )
+}
+
+pub fn git_tracked_files(path: &String) -> Option<Vec<String>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .arg("ls-files")
+        .arg("--cached")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(
+        output
+            .stdout
+            .get_string()
+            .lines()
+            .map(String::from)
+            .collect(),
+    )
+}
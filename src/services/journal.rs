@@ -0,0 +1,475 @@
+use crate::models::structs::{Journal, JournalOperation};
+use crate::services::operations::{self, is_dry_run};
+use crate::services::pack::pack_files;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+pub fn journal_path(root_path: &Path) -> PathBuf {
+    let mut path = PathBuf::from(root_path);
+    path.push(".repo");
+    path.push("journal.json");
+    path
+}
+
+/// Journaled run of filesystem operations: each operation is written to the journal
+/// before it is performed, so an interrupted run can be undone or replayed.
+pub struct Transaction {
+    path: PathBuf,
+    journal: Journal,
+}
+
+impl Transaction {
+    /// Starts a run, rolling back first the journal a previous run left incomplete.
+    pub fn begin(root_path: &Path, action: &str, project: &str) -> Self {
+        let path = journal_path(root_path);
+        if !is_dry_run() {
+            if let Some(journal) = Journal::read(&path) {
+                println!(
+                    "Found an incomplete {} of {}, rolling it back...",
+                    journal.action, journal.project
+                );
+                rollback(&path, &journal);
+            }
+        }
+        let transaction = Self {
+            path,
+            journal: Journal::new(action, project),
+        };
+        transaction.save().expect("error writing journal");
+        transaction
+    }
+
+    pub fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        self.perform(JournalOperation::Rename {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        })
+    }
+
+    pub fn symlink(&mut self, target: &Path, link: &Path) -> io::Result<()> {
+        self.perform(JournalOperation::Symlink {
+            target: target.to_path_buf(),
+            link: link.to_path_buf(),
+        })
+    }
+
+    pub fn copy_package(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        self.perform(JournalOperation::Copy {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        })
+    }
+
+    /// Installs package tarballs in a project, which puts the packages at `paths`.
+    pub fn install_packages(
+        &mut self,
+        project: &Path,
+        archives: &[PathBuf],
+        paths: &[PathBuf],
+    ) -> io::Result<()> {
+        self.perform(JournalOperation::Install {
+            project: project.to_path_buf(),
+            archives: archives.to_vec(),
+            paths: paths.to_vec(),
+        })
+    }
+
+    /// Removes a symlink or a copy of the given source package.
+    pub fn remove(&mut self, path: &Path, source: &Path) -> io::Result<()> {
+        let operation = match fs::read_link(path) {
+            Ok(target) => JournalOperation::RemoveLink {
+                target,
+                link: path.to_path_buf(),
+            },
+            Err(_) => JournalOperation::RemoveCopy {
+                from: source.to_path_buf(),
+                to: path.to_path_buf(),
+            },
+        };
+        self.perform(operation)
+    }
+
+    /// Ends the run: on success the journal is discarded, on failure every recorded
+    /// operation is undone and the process exits.
+    pub fn finish<T>(self, result: io::Result<T>) -> T {
+        self.commit(result).unwrap_or_else(|_| exit(1))
+    }
+
+    /// Ends the run like `finish`, but returns the error once rolled back.
+    pub fn commit<T>(self, result: io::Result<T>) -> io::Result<T> {
+        match result {
+            Ok(value) => {
+                if !is_dry_run() {
+                    fs::remove_file(&self.path).expect("error removing journal");
+                }
+                Ok(value)
+            }
+            Err(e) => {
+                println!("Error: {}, rolling back...", e);
+                rollback(&self.path, &self.journal);
+                Err(e)
+            }
+        }
+    }
+
+    fn perform(&mut self, operation: JournalOperation) -> io::Result<()> {
+        self.journal.operations.push(operation.clone());
+        self.save()?;
+        apply(&operation)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if is_dry_run() {
+            return Ok(());
+        }
+        self.journal.write(&self.path)
+    }
+}
+
+/// Undoes or replays the operations of an incomplete journal, then discards it.
+pub fn recover(root_path: &Path, replay: bool) {
+    let path = journal_path(root_path);
+    let journal = match Journal::read(&path) {
+        Some(journal) => journal,
+        None => {
+            println!("Nothing to recover.");
+            return;
+        }
+    };
+    if replay {
+        println!("Replaying {} of {}...", journal.action, journal.project);
+        let failed = journal
+            .operations
+            .iter()
+            .filter(|operation| {
+                println!("=> {}", operation);
+                redo(operation)
+                    .map_err(|e| println!("Cannot replay {}: {}", operation, e))
+                    .is_err()
+            })
+            .count();
+        if failed > 0 {
+            println!("{} operations could not be replayed.", failed);
+            exit(1);
+        }
+        if !is_dry_run() {
+            fs::remove_file(&path).expect("error removing journal");
+        }
+    } else {
+        println!("Undoing {} of {}...", journal.action, journal.project);
+        rollback(&path, &journal);
+    }
+}
+
+/// Undoes the journal operations in reverse order. Operations that were recorded but
+/// not performed are left alone. The journal is kept if anything could not be undone.
+fn rollback(path: &Path, journal: &Journal) {
+    let failed = journal
+        .operations
+        .iter()
+        .rev()
+        .filter(|operation| {
+            undo(operation)
+                .map_err(|e| println!("Cannot undo {}: {}", operation, e))
+                .is_err()
+        })
+        .count();
+    if failed > 0 {
+        println!(
+            "{} operations could not be undone, run `repo recover` once fixed.",
+            failed
+        );
+        exit(1);
+    }
+    if !is_dry_run() {
+        fs::remove_file(path).expect("error removing journal");
+    }
+}
+
+fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+fn is_link_to(link: &Path, target: &Path) -> bool {
+    fs::read_link(link).is_ok_and(|t| t == target)
+}
+
+fn apply(operation: &JournalOperation) -> io::Result<()> {
+    match operation {
+        JournalOperation::Rename { from, to } => {
+            if exists(to) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists", to.display()),
+                ));
+            }
+            operations::rename(from, to)
+        }
+        JournalOperation::Symlink { target, link } => operations::symlink(target, link),
+        JournalOperation::Copy { from, to } => operations::copy_files(from, to, &pack_files(from)),
+        JournalOperation::RemoveLink { link, .. } => operations::remove_all(link),
+        JournalOperation::RemoveCopy { to, .. } => operations::remove_all(to),
+        JournalOperation::Install {
+            project, archives, ..
+        } => operations::install_packages(project, archives),
+    }
+}
+
+/// Performs an operation again, skipping it when its effect is already in place.
+fn redo(operation: &JournalOperation) -> io::Result<()> {
+    match operation {
+        JournalOperation::Rename { from, to } if exists(from) && !exists(to) => apply(operation),
+        JournalOperation::Symlink { link, .. } if !exists(link) => apply(operation),
+        JournalOperation::Copy { to, .. } => {
+            operations::remove_all(to)?;
+            apply(operation)
+        }
+        JournalOperation::Install { paths, .. } => {
+            paths
+                .iter()
+                .try_for_each(|path| operations::remove_all(path))?;
+            apply(operation)
+        }
+        JournalOperation::RemoveLink { target, link } if is_link_to(link, target) => {
+            apply(operation)
+        }
+        JournalOperation::RemoveCopy { .. } => apply(operation),
+        _ => Ok(()),
+    }
+}
+
+/// Reverts an operation, skipping it when it was not performed.
+fn undo(operation: &JournalOperation) -> io::Result<()> {
+    match operation {
+        JournalOperation::Rename { from, to } if exists(to) && !exists(from) => {
+            operations::rename(to, from)
+        }
+        JournalOperation::Symlink { target, link } if is_link_to(link, target) => {
+            operations::remove_all(link)
+        }
+        JournalOperation::Copy { to, .. } => operations::remove_all(to),
+        JournalOperation::Install { paths, .. } => paths
+            .iter()
+            .try_for_each(|path| operations::remove_all(path)),
+        JournalOperation::RemoveLink { target, link } if !exists(link) => {
+            operations::symlink(target, link)
+        }
+        JournalOperation::RemoveCopy { from, to } if !exists(to) => {
+            operations::copy_files(from, to, &pack_files(from))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Metarepo with a local package `lib` and its registry version installed in `app`.
+    struct Fixture {
+        root: PathBuf,
+        source: PathBuf,
+        installed: PathBuf,
+        backup: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("repo-journal-{}-{}", name, std::process::id()));
+            fs::remove_dir_all(&root).ok();
+            let fixture = Self {
+                source: root.join("lib"),
+                installed: root.join("app/node_modules/lib"),
+                backup: root.join(".repo/backups/app/node_modules/lib"),
+                root,
+            };
+            write_package(&fixture.source, "local");
+            write_package(&fixture.installed, "registry");
+            fixture
+        }
+
+        fn installed_version(&self) -> String {
+            fs::read_to_string(self.installed.join("index.js")).unwrap()
+        }
+
+        fn journal(&self) -> Option<Journal> {
+            Journal::read(&journal_path(&self.root))
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    fn write_package(path: &Path, version: &str) {
+        fs::create_dir_all(path).unwrap();
+        fs::write(path.join("package.json"), r#"{"name":"lib"}"#).unwrap();
+        fs::write(path.join("index.js"), version).unwrap();
+    }
+
+    #[test]
+    fn a_failed_run_is_rolled_back() {
+        let fixture = Fixture::new("failed");
+        let mut transaction = Transaction::begin(&fixture.root, "link", "app");
+        let result = transaction
+            .rename(&fixture.installed, &fixture.backup)
+            .and_then(|_| transaction.symlink(&fixture.source, &fixture.installed))
+            .and_then(|_| {
+                // fails: the source of the rename does not exist
+                transaction.rename(&fixture.root.join("missing"), &fixture.root.join("other"))
+            });
+        assert!(result.is_err());
+        assert!(fs::read_link(&fixture.installed).is_ok());
+        assert!(transaction.commit(result).is_err());
+        assert!(!fixture.installed.is_symlink());
+        assert_eq!(fixture.installed_version(), "registry");
+        assert!(!exists(&fixture.backup));
+        assert!(fixture.journal().is_none());
+    }
+
+    #[test]
+    fn a_failed_copy_is_rolled_back() {
+        let fixture = Fixture::new("copy");
+        let mut transaction = Transaction::begin(&fixture.root, "copy", "app");
+        let result = transaction
+            .rename(&fixture.installed, &fixture.backup)
+            .and_then(|_| transaction.copy_package(&fixture.source, &fixture.installed))
+            .and_then(|_| transaction.rename(&fixture.backup, &fixture.installed));
+        // the backup cannot replace the copy
+        assert!(result.is_err());
+        assert_eq!(fixture.installed_version(), "local");
+        assert!(transaction.commit(result).is_err());
+        assert_eq!(fixture.installed_version(), "registry");
+        assert!(!exists(&fixture.backup));
+    }
+
+    #[test]
+    fn a_successful_run_discards_the_journal() {
+        let fixture = Fixture::new("success");
+        let mut transaction = Transaction::begin(&fixture.root, "link", "app");
+        assert!(fixture.journal().is_some());
+        let result = transaction
+            .rename(&fixture.installed, &fixture.backup)
+            .and_then(|_| transaction.symlink(&fixture.source, &fixture.installed));
+        assert!(transaction.commit(result).is_ok());
+        assert!(is_link_to(&fixture.installed, &fixture.source));
+        assert!(fixture.journal().is_none());
+    }
+
+    #[test]
+    fn an_interrupted_run_is_undone_on_recover() {
+        let fixture = Fixture::new("interrupted");
+        let mut transaction = Transaction::begin(&fixture.root, "link", "app");
+        transaction
+            .rename(&fixture.installed, &fixture.backup)
+            .unwrap();
+        transaction
+            .symlink(&fixture.source, &fixture.installed)
+            .unwrap();
+        // the process dies before committing
+        drop(transaction);
+        assert_eq!(fixture.journal().unwrap().operations.len(), 2);
+        recover(&fixture.root, false);
+        assert!(!fixture.installed.is_symlink());
+        assert_eq!(fixture.installed_version(), "registry");
+        assert!(fixture.journal().is_none());
+    }
+
+    #[test]
+    fn the_next_run_rolls_back_an_incomplete_journal() {
+        let fixture = Fixture::new("next");
+        let mut transaction = Transaction::begin(&fixture.root, "link", "app");
+        transaction
+            .rename(&fixture.installed, &fixture.backup)
+            .unwrap();
+        drop(transaction);
+        let transaction = Transaction::begin(&fixture.root, "unlink", "app");
+        assert_eq!(fixture.installed_version(), "registry");
+        assert!(!exists(&fixture.backup));
+        let journal = fixture.journal().unwrap();
+        assert_eq!(journal.action, "unlink");
+        assert!(journal.operations.is_empty());
+        assert!(transaction.commit(Ok(())).is_ok());
+    }
+
+    #[test]
+    fn operations_recorded_but_not_performed_are_not_undone() {
+        let fixture = Fixture::new("unperformed");
+        let mut journal = Journal::new("link", "app");
+        journal.operations = vec![
+            JournalOperation::Rename {
+                from: fixture.installed.to_path_buf(),
+                to: fixture.backup.to_path_buf(),
+            },
+            JournalOperation::Symlink {
+                target: fixture.source.to_path_buf(),
+                link: fixture.installed.to_path_buf(),
+            },
+        ];
+        // interrupted right after writing the journal
+        journal.write(&journal_path(&fixture.root)).unwrap();
+        recover(&fixture.root, false);
+        assert_eq!(fixture.installed_version(), "registry");
+        assert!(!exists(&fixture.backup));
+        assert!(fixture.journal().is_none());
+    }
+
+    #[test]
+    fn recover_replays_the_remaining_operations() {
+        let fixture = Fixture::new("replay");
+        let mut journal = Journal::new("link", "app");
+        journal.operations = vec![
+            JournalOperation::Rename {
+                from: fixture.installed.to_path_buf(),
+                to: fixture.backup.to_path_buf(),
+            },
+            JournalOperation::Symlink {
+                target: fixture.source.to_path_buf(),
+                link: fixture.installed.to_path_buf(),
+            },
+        ];
+        journal.write(&journal_path(&fixture.root)).unwrap();
+        // only the rename was performed before the interruption
+        apply(&journal.operations[0]).unwrap();
+        recover(&fixture.root, true);
+        assert!(is_link_to(&fixture.installed, &fixture.source));
+        assert_eq!(
+            fs::read_to_string(fixture.backup.join("index.js")).unwrap(),
+            "registry"
+        );
+        assert!(fixture.journal().is_none());
+    }
+
+    #[test]
+    fn recover_replays_copies_from_scratch() {
+        let fixture = Fixture::new("replay-copy");
+        let mut journal = Journal::new("copy", "app");
+        journal.operations = vec![
+            JournalOperation::Rename {
+                from: fixture.installed.to_path_buf(),
+                to: fixture.backup.to_path_buf(),
+            },
+            JournalOperation::Copy {
+                from: fixture.source.to_path_buf(),
+                to: fixture.installed.to_path_buf(),
+            },
+        ];
+        journal.write(&journal_path(&fixture.root)).unwrap();
+        // interrupted half way through the copy
+        apply(&journal.operations[0]).unwrap();
+        fs::create_dir_all(&fixture.installed).unwrap();
+        fs::write(fixture.installed.join("package.json"), "{").unwrap();
+        recover(&fixture.root, true);
+        assert_eq!(fixture.installed_version(), "local");
+        assert_eq!(
+            fs::read_to_string(fixture.installed.join("package.json")).unwrap(),
+            r#"{"name":"lib"}"#
+        );
+        assert!(fixture.journal().is_none());
+    }
+}
@@ -1,4 +1,13 @@
 pub mod actions;
+pub mod cache;
+pub mod executor;
+pub mod export;
+pub mod git;
+pub mod journal;
 pub mod n;
 pub mod npm;
+pub mod operations;
+pub mod pack;
+pub mod sync;
+pub mod watcher;
 pub mod yarn;
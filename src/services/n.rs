@@ -1,3 +1,4 @@
+use crate::services::operations::skip_command;
 use crate::traits::vec_traits::VecExtension;
 use std::process::Command;
 
@@ -6,6 +7,11 @@ pub fn set_node_version(version: &String) {
         return;
     }
     println!("Setting node version {}...", version);
-    let output = Command::new("n").arg(version).output().expect("n error!");
+    let mut command = Command::new("n");
+    command.arg(version);
+    if skip_command(&command) {
+        return;
+    }
+    let output = command.output().expect("n error!");
     output.stderr.log_and_die_if_exists();
 }
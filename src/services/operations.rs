@@ -0,0 +1,128 @@
+use std::fs;
+use std::io;
+use std::os::unix;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// When enabled, filesystem operations and commands are printed instead of performed.
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Prints the command and returns true when it must not be run.
+pub fn skip_command(command: &Command) -> bool {
+    if is_dry_run() {
+        let args: Vec<String> = command
+            .get_args()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect();
+        println!(
+            "[dry-run] {} {}",
+            command.get_program().to_string_lossy(),
+            args.join(" ")
+        );
+    }
+    is_dry_run()
+}
+
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    if is_dry_run() {
+        println!("[dry-run] mv {} {}", from.display(), to.display());
+        return Ok(());
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(from, to)
+}
+
+pub fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    if is_dry_run() {
+        println!("[dry-run] ln -s {} {}", target.display(), link.display());
+        return Ok(());
+    }
+    unix::fs::symlink(target, link)
+}
+
+/// Removes a file, a symlink or a whole directory.
+pub fn remove_all(path: &Path) -> io::Result<()> {
+    if is_dry_run() {
+        println!("[dry-run] rm -rf {}", path.display());
+        return Ok(());
+    }
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Copies the given files, relative to `from`, to the same place under `to`.
+pub fn copy_files(from: &Path, to: &Path, files: &[String]) -> io::Result<()> {
+    if is_dry_run() {
+        println!(
+            "[dry-run] copy {} files from {} to {}",
+            files.len(),
+            from.display(),
+            to.display()
+        );
+        return Ok(());
+    }
+    for file in files {
+        let target = to.join(file);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(from.join(file), target)?;
+    }
+    Ok(())
+}
+
+/// Manifests `yarn add` rewrites, put back once the package is installed.
+const MANIFESTS: [&str; 2] = ["package.json", "yarn.lock"];
+
+/// Installs tarballs made by `npm pack` or `yarn pack` in a project with `yarn add`, so
+/// their dependencies and binaries are installed too, then restores the project's
+/// package.json and lockfile.
+pub fn install_packages(project_path: &Path, archives: &[PathBuf]) -> io::Result<()> {
+    let mut command = Command::new("yarn");
+    command.arg("--cwd").arg(project_path).arg("add").args(
+        archives
+            .iter()
+            .map(|archive| format!("file:{}", archive.display())),
+    );
+    if skip_command(&command) {
+        return Ok(());
+    }
+    let manifests: Vec<(PathBuf, Option<Vec<u8>>)> = MANIFESTS
+        .iter()
+        .map(|manifest| {
+            let path = project_path.join(manifest);
+            let content = fs::read(&path).ok();
+            (path, content)
+        })
+        .collect();
+    let output = command.output();
+    for (path, content) in &manifests {
+        match content {
+            Some(content) => fs::write(path, content)?,
+            None => remove_all(path)?,
+        }
+    }
+    let output = output?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
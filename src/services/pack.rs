@@ -0,0 +1,488 @@
+use crate::models::structs::PackageJson;
+use std::fs;
+use std::path::Path;
+
+/// Files npm never packs, whatever `files` and the ignore files say.
+const ALWAYS_EXCLUDED: [&str; 19] = [
+    ".npmignore",
+    ".gitignore",
+    ".git",
+    "CVS",
+    ".svn",
+    ".hg",
+    ".lock-wscript",
+    ".wafpickle-*",
+    ".*.swp",
+    ".DS_Store",
+    "._*",
+    "npm-debug.log",
+    ".npmrc",
+    "node_modules",
+    "config.gypi",
+    "*.orig",
+    "/package-lock.json",
+    "/yarn.lock",
+    "/pnpm-lock.yaml",
+];
+
+/// Root files npm always packs, matched case-insensitively by prefix.
+const ALWAYS_INCLUDED: [&str; 4] = ["PACKAGE.JSON", "README", "LICENSE", "LICENCE"];
+
+/// Line of `files`, `.npmignore` or `.gitignore`.
+struct Rule {
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let line = line.strip_prefix("./").unwrap_or(line);
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/').to_string();
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Self {
+            pattern,
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// Whether the rule matches a path relative to the package root. Unanchored
+    /// patterns match at any depth.
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            glob_match(&self.pattern, path)
+        } else {
+            glob_match(&self.pattern, path.rsplit('/').next().unwrap_or(path))
+        }
+    }
+}
+
+/// Last matching rule wins, a negated one un-matching the path.
+fn matches_rules(rules: &[Rule], path: &str, is_dir: bool) -> bool {
+    rules.iter().fold(false, |matched, rule| {
+        if rule.matches(path, is_dir) {
+            !rule.negated
+        } else {
+            matched
+        }
+    })
+}
+
+/// Whether the `files` entries include a file, matching it or one of its parent
+/// folders. Later entries override earlier ones, so a negation can leave out files of
+/// an included folder.
+fn is_included(rules: &[Rule], file: &str, parents: &[String]) -> bool {
+    rules.iter().fold(false, |included, rule| {
+        if rule.matches(file, false) || parents.iter().any(|dir| rule.matches(dir, true)) {
+            !rule.negated
+        } else {
+            included
+        }
+    })
+}
+
+fn read_rules(path: &Path) -> Option<Vec<Rule>> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|content| content.lines().filter_map(Rule::parse).collect())
+}
+
+/// Files `npm pack` would put in the package, relative to its path: the entries of
+/// package.json `files` or, without it, everything not excluded by `.npmignore` (or
+/// `.gitignore` when there is none), plus package.json, README, LICENSE and `main`.
+pub fn pack_files(package_path: &Path) -> Vec<String> {
+    let package =
+        PackageJson::read(&package_path.display().to_string(), package_path).unwrap_or_default();
+    let excluded: Vec<Rule> = ALWAYS_EXCLUDED
+        .iter()
+        .filter_map(|r| Rule::parse(r))
+        .collect();
+    let mut files = Vec::<String>::new();
+    match &package.files {
+        Some(entries) => {
+            // entries of files are relative to the package root
+            let included: Vec<Rule> = entries
+                .iter()
+                .filter_map(|r| Rule::parse(r))
+                .map(|rule| Rule {
+                    anchored: true,
+                    ..rule
+                })
+                .collect();
+            walk(
+                package_path,
+                "",
+                &excluded,
+                &mut |file, parents| is_included(&included, file, parents),
+                &mut files,
+            );
+        }
+        None => {
+            let ignored: Vec<Rule> = read_rules(&package_path.join(".npmignore"))
+                .or_else(|| read_rules(&package_path.join(".gitignore")))
+                .unwrap_or_default();
+            let rules: Vec<Rule> = excluded.into_iter().chain(ignored).collect();
+            walk(package_path, "", &rules, &mut |_, _| true, &mut files);
+        }
+    }
+    if let Ok(entries) = fs::read_dir(package_path) {
+        entries.flatten().for_each(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let upper = name.to_uppercase();
+            if entry.path().is_file() && ALWAYS_INCLUDED.iter().any(|p| upper.starts_with(p)) {
+                files.push(name);
+            }
+        });
+    }
+    if let Some(main) = &package.main {
+        let main = main.trim_start_matches("./").to_string();
+        if package_path.join(&main).is_file() {
+            files.push(main);
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Collects the files under `dir` that are not ignored and that `include` accepts,
+/// given their path and the paths of their parent folders. Ignored folders are not
+/// descended into.
+fn walk(
+    root: &Path,
+    dir: &str,
+    ignored: &[Rule],
+    include: &mut dyn FnMut(&str, &[String]) -> bool,
+    files: &mut Vec<String>,
+) {
+    let entries = match fs::read_dir(root.join(dir)) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    entries.flatten().for_each(|entry| {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = if dir.is_empty() {
+            name
+        } else {
+            format!("{}/{}", dir, name)
+        };
+        let is_dir = entry.path().is_dir();
+        if matches_rules(ignored, &path, is_dir) {
+            return;
+        }
+        if is_dir {
+            walk(root, &path, ignored, include, files);
+        } else {
+            let parents: Vec<String> = path
+                .match_indices('/')
+                .map(|(i, _)| path[..i].to_string())
+                .collect();
+            if include(&path, &parents) {
+                files.push(path);
+            }
+        }
+    });
+}
+
+/// Matches a path against a glob where `*` and `?` stay within a path segment and `**`
+/// spans any number of segments.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    let patterns: Vec<&str> = pattern.split('/').collect();
+    let segments: Vec<&str> = path.split('/').collect();
+    match_segments(&patterns, &segments)
+}
+
+fn match_segments(patterns: &[&str], segments: &[&str]) -> bool {
+    match (patterns.first(), segments.first()) {
+        (None, None) => true,
+        (Some(&"**"), _) => {
+            match_segments(&patterns[1..], segments)
+                || (!segments.is_empty() && match_segments(patterns, &segments[1..]))
+        }
+        (Some(pattern), Some(segment)) => {
+            match_segment(pattern.as_bytes(), segment.as_bytes())
+                && match_segments(&patterns[1..], &segments[1..])
+        }
+        _ => false,
+    }
+}
+
+fn match_segment(pattern: &[u8], segment: &[u8]) -> bool {
+    match (pattern.first(), segment.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            match_segment(&pattern[1..], segment)
+                || (!segment.is_empty() && match_segment(pattern, &segment[1..]))
+        }
+        (Some(b'?'), Some(_)) => match_segment(&pattern[1..], &segment[1..]),
+        (Some(p), Some(s)) => p == s && match_segment(&pattern[1..], &segment[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Empty package folder, unique to the test.
+    fn package(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("repo-pack-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&path).ok();
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn write(package: &Path, file: &str, content: &str) {
+        let path = package.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn rules(lines: &[&str]) -> Vec<Rule> {
+        lines.iter().filter_map(|l| Rule::parse(l)).collect()
+    }
+
+    #[test]
+    fn parse_skips_blank_lines_and_comments() {
+        assert!(Rule::parse("").is_none());
+        assert!(Rule::parse("   ").is_none());
+        assert!(Rule::parse("# dist").is_none());
+        assert!(Rule::parse("/").is_none());
+    }
+
+    #[test]
+    fn parse_reads_negation_anchoring_and_folders() {
+        let rule = Rule::parse("!dist/").unwrap();
+        assert_eq!(rule.pattern, "dist");
+        assert!(rule.negated && rule.dir_only && !rule.anchored);
+        let rule = Rule::parse("/yarn.lock").unwrap();
+        assert_eq!(rule.pattern, "yarn.lock");
+        assert!(rule.anchored && !rule.negated && !rule.dir_only);
+        let rule = Rule::parse("src/*.test.js").unwrap();
+        assert!(rule.anchored);
+        let rule = Rule::parse("./lib").unwrap();
+        assert_eq!(rule.pattern, "lib");
+    }
+
+    #[test]
+    fn glob_stars_stay_within_a_segment() {
+        let cases = [
+            ("*.js", "index.js", true),
+            ("*.js", "src/index.js", false),
+            ("src/*.js", "src/index.js", true),
+            ("src/*.js", "src/lib/index.js", false),
+            ("?.js", "a.js", true),
+            ("?.js", "ab.js", false),
+            ("index.js", "index.jsx", false),
+            (".*.swp", ".index.js.swp", true),
+        ];
+        for (pattern, path, expected) in cases {
+            assert_eq!(glob_match(pattern, path), expected, "{} {}", pattern, path);
+        }
+    }
+
+    #[test]
+    fn glob_double_stars_span_segments() {
+        let cases = [
+            ("**/*.test.js", "a.test.js", true),
+            ("**/*.test.js", "src/sub/a.test.js", true),
+            ("dist/**", "dist/a/b.js", true),
+            ("dist/**/*.map", "dist/index.js.map", true),
+            ("dist/**/*.map", "dist/a/b/index.js.map", true),
+            ("dist/**/*.map", "lib/index.js.map", false),
+            ("a/**/b", "a/b", true),
+            ("a/**/b", "a/x/y/b", true),
+            ("a/**/b", "a/x/y/c", false),
+        ];
+        for (pattern, path, expected) in cases {
+            assert_eq!(glob_match(pattern, path), expected, "{} {}", pattern, path);
+        }
+    }
+
+    #[test]
+    fn unanchored_rules_match_at_any_depth() {
+        let rule = Rule::parse("*.log").unwrap();
+        assert!(rule.matches("debug.log", false));
+        assert!(rule.matches("logs/debug.log", false));
+        let rule = Rule::parse("/tmp").unwrap();
+        assert!(rule.matches("tmp", true));
+        assert!(!rule.matches("src/tmp", true));
+    }
+
+    #[test]
+    fn folder_rules_do_not_match_files() {
+        let rule = Rule::parse("build/").unwrap();
+        assert!(rule.matches("build", true));
+        assert!(rule.matches("src/build", true));
+        assert!(!rule.matches("build", false));
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let rules = rules(&["*.js", "!keep.js", "src/keep.js"]);
+        assert!(matches_rules(&rules, "index.js", false));
+        assert!(!matches_rules(&rules, "keep.js", false));
+        assert!(matches_rules(&rules, "src/keep.js", false));
+        assert!(!matches_rules(&rules, "README.md", false));
+    }
+
+    #[test]
+    fn included_folders_can_be_narrowed_by_negations() {
+        let rules: Vec<Rule> = rules(&["dist", "!dist/**/*.test.js", "dist/keep.test.js"])
+            .into_iter()
+            .map(|rule| Rule {
+                anchored: true,
+                ..rule
+            })
+            .collect();
+        let parents =
+            |dirs: &[&str]| -> Vec<String> { dirs.iter().map(|d| d.to_string()).collect() };
+        assert!(is_included(&rules, "dist/index.js", &parents(&["dist"])));
+        assert!(!is_included(
+            &rules,
+            "dist/sub/a.test.js",
+            &parents(&["dist", "dist/sub"])
+        ));
+        assert!(is_included(
+            &rules,
+            "dist/keep.test.js",
+            &parents(&["dist"])
+        ));
+        assert!(!is_included(&rules, "src/index.js", &parents(&["src"])));
+    }
+
+    #[test]
+    fn pack_files_follows_the_files_entries() {
+        let path = package("files");
+        write(
+            &path,
+            "package.json",
+            r#"{"name":"lib","main":"index.js","files":["dist","!dist/**/*.test.js","types/*.d.ts"]}"#,
+        );
+        for file in [
+            "index.js",
+            "README.md",
+            "LICENSE",
+            "src/index.ts",
+            "dist/index.js",
+            "dist/sub/a.js",
+            "dist/sub/a.test.js",
+            "dist/.DS_Store",
+            "dist/node_modules/dep/index.js",
+            "types/index.d.ts",
+            "types/sub/index.d.ts",
+        ] {
+            write(&path, file, "");
+        }
+        assert_eq!(
+            pack_files(&path),
+            vec![
+                "LICENSE",
+                "README.md",
+                "dist/index.js",
+                "dist/sub/a.js",
+                "index.js",
+                "package.json",
+                "types/index.d.ts",
+            ]
+        );
+        fs::remove_dir_all(&path).ok();
+    }
+
+    #[test]
+    fn pack_files_applies_npmignore_over_gitignore() {
+        let path = package("npmignore");
+        write(&path, "package.json", r#"{"name":"lib"}"#);
+        write(
+            &path,
+            ".npmignore",
+            "# sources\n*.log\n/tmp\nbuild/\n!keep.log\n",
+        );
+        write(&path, ".gitignore", "dist\n");
+        for file in [
+            "dist/index.js",
+            "debug.log",
+            "keep.log",
+            "logs/error.log",
+            "tmp/a.js",
+            "src/tmp/a.js",
+            "build/index.js",
+            "src/build/index.js",
+        ] {
+            write(&path, file, "");
+        }
+        assert_eq!(
+            pack_files(&path),
+            vec!["dist/index.js", "keep.log", "package.json", "src/tmp/a.js"]
+        );
+        fs::remove_dir_all(&path).ok();
+    }
+
+    #[test]
+    fn pack_files_falls_back_to_gitignore() {
+        let path = package("gitignore");
+        write(&path, "package.json", r#"{"name":"lib"}"#);
+        write(&path, ".gitignore", "dist\n*.tgz\n");
+        for file in ["dist/index.js", "lib-1.0.0.tgz", "src/index.js"] {
+            write(&path, file, "");
+        }
+        assert_eq!(pack_files(&path), vec!["package.json", "src/index.js"]);
+        fs::remove_dir_all(&path).ok();
+    }
+
+    #[test]
+    fn pack_files_always_excludes_and_includes_the_npm_defaults() {
+        let path = package("defaults");
+        write(&path, "package.json", r#"{"name":"lib"}"#);
+        write(&path, ".npmignore", "README*\nLicence*\n");
+        for file in [
+            "README.md",
+            "Licence.txt",
+            "CHANGELOG.md",
+            ".npmrc",
+            "yarn.lock",
+            "package-lock.json",
+            ".git/HEAD",
+            "node_modules/dep/index.js",
+            "src/index.js",
+            "src/index.js.orig",
+            "src/.index.js.swp",
+            "src/yarn.lock",
+            "src/npm-debug.log",
+        ] {
+            write(&path, file, "");
+        }
+        assert_eq!(
+            pack_files(&path),
+            vec![
+                "CHANGELOG.md",
+                "Licence.txt",
+                "README.md",
+                "package.json",
+                "src/index.js",
+                "src/yarn.lock",
+            ]
+        );
+        fs::remove_dir_all(&path).ok();
+    }
+}
@@ -0,0 +1,70 @@
+use crate::services::cache::walk_files;
+use crate::services::operations::{copy_files, remove_all};
+use crate::services::pack::pack_files;
+use std::fs;
+use std::path::Path;
+use std::slice;
+
+const SKIPPED_FOLDERS: [&str; 2] = ["node_modules", ".git"];
+
+/// Every file of a package or of a copy of it, except for its own dependencies,
+/// relative to its path.
+pub fn package_files(package_path: &Path) -> Vec<String> {
+    let mut files = Vec::<String>::new();
+    walk_files(package_path, package_path, &SKIPPED_FOLDERS, &mut files);
+    files.sort();
+    files
+}
+
+/// Packed files of a source package that differ in its copy, and files the copy has
+/// that the source no longer packs.
+pub fn package_drift(source_path: &Path, copy_path: &Path) -> (Vec<String>, Vec<String>) {
+    let sources = pack_files(source_path);
+    let removed = package_files(copy_path)
+        .into_iter()
+        .filter(|file| !sources.contains(file))
+        .collect();
+    let outdated = sources
+        .into_iter()
+        .filter(|file| is_outdated(&source_path.join(file), &copy_path.join(file)))
+        .collect();
+    (outdated, removed)
+}
+
+/// Brings a copied package up to date with its source: copies the files that are
+/// missing or older in the copy and removes the ones deleted from the source. Returns
+/// the number of updated and removed files.
+pub fn mirror_package(source_path: &Path, copy_path: &Path) -> (usize, usize) {
+    let (outdated, removed) = package_drift(source_path, copy_path);
+    let updated = outdated
+        .iter()
+        .filter(|file| {
+            copy_files(source_path, copy_path, slice::from_ref(file))
+                .map_err(|e| println!("Cannot copy {}: {}", copy_path.join(file).display(), e))
+                .is_ok()
+        })
+        .count();
+    let removed = removed
+        .iter()
+        .filter(|file| {
+            remove_all(&copy_path.join(file))
+                .map_err(|e| println!("Cannot remove {}: {}", copy_path.join(file).display(), e))
+                .is_ok()
+        })
+        .count();
+    (updated, removed)
+}
+
+fn is_outdated(source: &Path, copy: &Path) -> bool {
+    match (fs::metadata(source), fs::metadata(copy)) {
+        (Ok(source), Ok(copy)) => {
+            source.len() != copy.len()
+                || match (source.modified(), copy.modified()) {
+                    (Ok(source), Ok(copy)) => source > copy,
+                    _ => true,
+                }
+        }
+        (Ok(_), Err(_)) => true,
+        _ => false,
+    }
+}
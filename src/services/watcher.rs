@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+type Snapshot = HashMap<String, Option<SystemTime>>;
+
+/// Polls the files of a set of projects, as listed by `files`, for modifications,
+/// additions and deletions.
+pub struct Watcher {
+    projects: Vec<(String, PathBuf)>,
+    snapshots: HashMap<String, Snapshot>,
+    debounce: Duration,
+    files: fn(&Path) -> Vec<String>,
+}
+
+impl Watcher {
+    pub fn new(
+        projects: Vec<(String, PathBuf)>,
+        debounce: Duration,
+        files: fn(&Path) -> Vec<String>,
+    ) -> Self {
+        let snapshots = projects
+            .iter()
+            .map(|(project, path)| (project.to_string(), snapshot(path, files)))
+            .collect();
+        Self {
+            projects,
+            snapshots,
+            debounce,
+            files,
+        }
+    }
+
+    /// Blocks until some watched project changed and no further change happened for the
+    /// debounce delay, then returns every project changed during the burst.
+    pub fn wait(&mut self) -> Vec<String> {
+        let mut changed = Vec::<String>::new();
+        loop {
+            let found = self.poll();
+            if found.is_empty() && !changed.is_empty() {
+                break;
+            }
+            found.into_iter().for_each(|p| {
+                if !changed.contains(&p) {
+                    changed.push(p);
+                }
+            });
+            thread::sleep(if changed.is_empty() {
+                POLL_INTERVAL
+            } else {
+                self.debounce
+            });
+        }
+        changed.sort();
+        changed
+    }
+
+    /// Takes a new snapshot of the projects, so changes made meanwhile (e.g. build
+    /// outputs) are not reported.
+    pub fn refresh(&mut self, projects: &[String]) {
+        for (project, path) in self.projects.iter().filter(|(p, _)| projects.contains(p)) {
+            self.snapshots
+                .insert(project.to_string(), snapshot(path, self.files));
+        }
+    }
+
+    fn poll(&mut self) -> Vec<String> {
+        let mut changed = Vec::<String>::new();
+        for (project, path) in &self.projects {
+            let current = snapshot(path, self.files);
+            if self.snapshots.get(project) != Some(&current) {
+                changed.push(project.to_string());
+                self.snapshots.insert(project.to_string(), current);
+            }
+        }
+        changed
+    }
+}
+
+fn snapshot(project_path: &Path, files: fn(&Path) -> Vec<String>) -> Snapshot {
+    files(project_path)
+        .into_iter()
+        .map(|file| {
+            let modified = fs::metadata(project_path.join(&file))
+                .and_then(|m| m.modified())
+                .ok();
+            (file, modified)
+        })
+        .collect()
+}
@@ -1,23 +1,45 @@
+use crate::services::operations::skip_command;
 use crate::traits::vec_traits::*;
-use std::process::Command;
+use std::process::{Command, Output};
 
 pub fn yarn_install(path: &String) {
     println!("Running yarn install...");
-    let output = Command::new("yarn")
+    let mut command = Command::new("yarn");
+    command
         .arg("--cwd")
-        .arg(&path.to_string())
+        .arg(path)
         .arg("install")
-        .arg("--frozen-lockfile")
-        .output()
-        .expect("Yarn error!");
+        .arg("--frozen-lockfile");
+    if skip_command(&command) {
+        return;
+    }
+    let output = command.output().expect("Yarn error!");
     output.stderr.log();
 }
 
+/// Packs the project into the given tarball, returning whether it succeeded.
+pub fn yarn_pack(path: &String, filename: &String) -> bool {
+    println!("Running yarn pack...");
+    let mut command = Command::new("yarn");
+    command
+        .arg("--cwd")
+        .arg(path)
+        .arg("pack")
+        .arg("--filename")
+        .arg(filename);
+    if skip_command(&command) {
+        return true;
+    }
+    let output = command.output().expect("Yarn error!");
+    output.stderr.log();
+    output.status.success()
+}
+
 pub fn yarn_build(path: &String) {
     println!("Running yarn build...");
     let output = Command::new("yarn")
         .arg("--cwd")
-        .arg(&path.to_string())
+        .arg(path)
         .arg("build")
         .output()
         .expect("Yarn error!");
@@ -28,43 +50,58 @@ pub fn yarn_outdated(path: &String) -> String {
     println!("Running yarn outdated...");
     let output = Command::new("yarn")
         .arg("--cwd")
-        .arg(&path.to_string())
+        .arg(path)
         .arg("outdated")
         .arg("--json")
         .output()
         .expect("Yarn error!");
     output.stderr.log();
     let output_json = output.stdout.get_string_or_die();
-    return output_json
+    output_json
         .split('\n')
-        .skip(1)
-        .next()
+        .nth(1)
         .expect("Cannot split yarn outdated json result.")
-        .to_string();
+        .to_string()
 }
 
 pub fn yarn_add(path: &String, package: &String, version: &String) {
     println!("Running yarn add {}@^{}...", package, version);
-    let output = Command::new("yarn")
+    let mut command = Command::new("yarn");
+    command
         .arg("--cwd")
-        .arg(&path.to_string())
+        .arg(path)
         .arg("add")
-        .arg(format!("{}@^{}", package, version))
-        .output()
-        .expect("Yarn error!");
+        .arg(format!("{}@^{}", package, version));
+    if skip_command(&command) {
+        return;
+    }
+    let output = command.output().expect("Yarn error!");
     output.stderr.log();
     output.stdout.log();
 }
 
 pub fn yarn_upgrade(path: &String, package: &String, version: &String) {
     println!("Running yarn upgrade {}@^{}...", package, version);
-    let output = Command::new("yarn")
+    let mut command = Command::new("yarn");
+    command
         .arg("--cwd")
-        .arg(&path.to_string())
+        .arg(path)
         .arg("upgrade")
-        .arg(format!("{}@^{}", package, version))
-        .output()
-        .expect("Yarn error!");
+        .arg(format!("{}@^{}", package, version));
+    if skip_command(&command) {
+        return;
+    }
+    let output = command.output().expect("Yarn error!");
     output.stderr.log();
     output.stdout.log();
 }
+
+pub fn yarn_run_output(path: &String, script: &String) -> Output {
+    Command::new("yarn")
+        .arg("--cwd")
+        .arg(path)
+        .arg("run")
+        .arg(script)
+        .output()
+        .expect("Yarn error!")
+}
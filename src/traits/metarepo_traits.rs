@@ -1,39 +1,93 @@
-use crate::models::structs::RepositoryItem;
+use crate::models::graph::{DependencyGraph, TraversalPlan};
+use crate::models::structs::{
+    BuildCache, Dependency, DependencyKind, GraphFormat, LinkState, MetaFile, MetaFileError,
+    MetaProject, PackageJson, PackageJsonError, RepoState, RepositoryItem,
+};
+use crate::services::cache::{
+    cache_path, has_outputs, hash_project, output_paths, restore_artifacts, source_files,
+    store_artifacts,
+};
+use crate::services::executor::{execute, print_reports, Task, TaskReport, TaskStatus};
+use crate::services::export::export_graph;
+use crate::services::git::git_changed_files;
+use crate::services::journal::Transaction;
 use crate::services::n::*;
+use crate::services::operations::{self, is_dry_run};
+use crate::services::sync::{mirror_package, package_drift, package_files};
+use crate::services::watcher::Watcher;
 use crate::services::yarn::*;
 use crate::traits::string_traits::StringExtension;
+use crate::traits::versioning_traits::Versioning;
 use semver::Version;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::os::unix;
-use std::path::PathBuf;
-use std::process::Command;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command, Output};
+use std::time::{Duration, Instant};
 
 pub trait MetarepoExtension {
-    fn get_projects(self) -> Vec<String>;
+    fn get_projects(self) -> Vec<MetaProject>;
     fn set_node_version(self);
-    fn map_repository(self) -> HashMap<String, RepositoryItem>;
-    fn package_to_link(self, project: &String);
-    fn link_to_package(self, project: &String);
-    fn copy_packages(self, project: &String);
-    fn update_dependencies(self, project: &String);
-    fn build_tree(self, project: &String);
-    fn clean_tree(self, project: &String);
+    fn map_repository(self, kinds: &[DependencyKind]) -> DependencyGraph;
+    fn package_to_link(self, project: &str, kinds: &[DependencyKind], force: bool);
+    fn link_to_package(self, project: &str, kinds: &[DependencyKind]);
+    fn copy_packages(self, project: &str, kinds: &[DependencyKind], force: bool);
+    fn pack_packages(self, project: &str, kinds: &[DependencyKind], force: bool);
+    fn unpack_packages(self, project: &str, kinds: &[DependencyKind]);
+    fn sync_packages(self, project: &str, kinds: &[DependencyKind], force: bool, debounce: u64);
+    fn update_dependencies(self, project: &str, kinds: &[DependencyKind]);
+    fn build_tree(
+        self,
+        project: &str,
+        kinds: &[DependencyKind],
+        since: Option<&str>,
+        jobs: usize,
+        no_cache: bool,
+    );
+    fn run_script(
+        self,
+        project: &str,
+        script: &str,
+        kinds: &[DependencyKind],
+        since: Option<&str>,
+        reverse: bool,
+        jobs: usize,
+    );
+    fn exec_command(
+        self,
+        project: &str,
+        command: &[String],
+        kinds: &[DependencyKind],
+        parallel: bool,
+        jobs: usize,
+    );
+    fn watch_tree(self, project: &str, kinds: &[DependencyKind], debounce: u64);
+    fn clean_tree(self, project: &str, kinds: &[DependencyKind]);
+    fn print_status(self, project: &str, kinds: &[DependencyKind]);
+    fn restore_backups(self, project: &str, kinds: &[DependencyKind], all: bool);
+    fn print_graph(
+        self,
+        project: &str,
+        kinds: &[DependencyKind],
+        format: GraphFormat,
+        show_kinds: bool,
+    );
+    fn print_dependents(self, module: &str, kinds: &[DependencyKind], format: GraphFormat);
+    fn print_affected(self, since: &str, kinds: &[DependencyKind], format: GraphFormat);
+    fn print_why(self, project: &str, module: &str, kinds: &[DependencyKind]);
 }
 
 impl MetarepoExtension for &PathBuf {
-    fn get_projects(self) -> Vec<String> {
-        let mut root_path = PathBuf::from(self);
-        println!("Getting projects from .meta file...");
-        root_path.push(".meta");
-        let meta_content =
-            std::fs::read_to_string(root_path).expect("Can't find .meta file in given path!");
-        let json: serde_json::Value =
-            serde_json::from_str(&meta_content).expect("File .meta was not well-formatted!");
-        let projects = json["projects"]
-            .as_object()
-            .expect("Projects are not in valid format!");
-        return projects.keys().map(|k| k.to_string()).collect();
+    fn get_projects(self) -> Vec<MetaProject> {
+        eprintln!("Getting projects from .meta file...");
+        let mut projects = Vec::<MetaProject>::new();
+        collect_meta_projects(self, "", &mut projects).unwrap_or_else(|e| {
+            println!("{}", e);
+            exit(1);
+        });
+        projects
     }
 
     fn set_node_version(self) {
@@ -45,275 +99,1379 @@ impl MetarepoExtension for &PathBuf {
         set_node_version(&node_version);
     }
 
-    fn map_repository(self) -> HashMap<String, RepositoryItem> {
+    fn map_repository(self, kinds: &[DependencyKind]) -> DependencyGraph {
         let projects = self.get_projects();
-        let mut repository = HashMap::<String, RepositoryItem>::new();
+        let mut packages = Vec::<(String, PackageJson)>::new();
 
         // map repository
-        let filter = |_: &&String| true;
-        let map_modules = |project_relative_path: &String| {
+        let filter = |_: &&MetaProject| true;
+        let map_modules = |project: &MetaProject| {
+            let project_relative_path = &project.path;
             let mut project_path = PathBuf::from(self);
-            project_path.push(project_relative_path.to_string());
-            let pkg = get_package_json(&project_path);
-            let ri = RepositoryItem::new(
-                project_relative_path.strip(),
-                pkg["name"].strip(),
-                Vec::<String>::new(),
-            );
-            repository.insert(project_relative_path.strip(), ri);
+            project_path.push(project_relative_path);
+            match PackageJson::read(project_relative_path, &project_path) {
+                Ok(pkg) => packages.push((project_relative_path.strip(), pkg)),
+                Err(PackageJsonError::Missing(_)) => {
+                    eprintln!("Skipping {}: no package.json found", project_relative_path)
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            }
         };
         iterate_projects(&projects, filter, map_modules);
 
         // map dependencies
-        let repo_modules: Vec<String> = repository.values().map(|k| k.module.to_string()).collect();
-        let filter = |_: &&String| true;
-        let map_dependencies = |project_relative_path: &String| {
-            let mut project_path = PathBuf::from(self);
-            project_path.push(project_relative_path.to_string());
-            let pkg = get_package_json(&project_path);
-            let dependencies = get_cross_dependencies(&pkg, &repo_modules);
-            let ri = RepositoryItem::new(
-                project_relative_path.strip(),
-                pkg["name"].strip(),
-                dependencies,
-            );
-            repository.insert(project_relative_path.strip(), ri);
-        };
-        iterate_projects(&projects, filter, map_dependencies);
+        let repo_modules: Vec<String> = packages.iter().map(|(_, pkg)| pkg.module()).collect();
+        let items = packages
+            .iter()
+            .map(|(project, pkg)| {
+                RepositoryItem::new(
+                    project.to_string(),
+                    pkg.module(),
+                    pkg.version.clone().unwrap_or_default(),
+                    get_cross_dependencies(pkg, &repo_modules, kinds),
+                )
+            })
+            .collect();
 
-        return repository;
+        DependencyGraph::new(items)
     }
 
-    fn package_to_link(self, project: &String) {
-        let mapped_repository = self.map_repository();
-        let link = |dep_path: &PathBuf, dep_repo_path: &PathBuf| {
+    fn package_to_link(self, project: &str, kinds: &[DependencyKind], force: bool) {
+        let (graph, plan) = get_plan(self, project, kinds);
+        check_versions(&graph, &plan, force);
+        let link = |transaction: &mut Transaction, dep: &InstalledDependency, state: LinkState| {
+            match state {
+                LinkState::Linked(target) if target == dep.source => {
+                    println!("=> {} already linked", dep.path.display());
+                    return Ok(false);
+                }
+                LinkState::Missing if !dep.has_backup() => {
+                    println!("=> Skipping {}: not installed", dep.path.display());
+                    return Ok(false);
+                }
+                _ => {}
+            }
             println!(
                 "=> Linking {} to {}",
-                dep_path.display().to_string(),
-                dep_repo_path.display().to_string()
+                dep.path.display(),
+                dep.source.display()
             );
-            fs::rename(&dep_path, dep_path.display().to_string() + "_orig")
-                .expect("error renaming directory");
-            unix::fs::symlink(&dep_repo_path, &dep_path).expect("error symlinking directory");
+            set_aside(transaction, dep, &state)?;
+            transaction.symlink(&dep.source, &dep.path)?;
+            Ok(true)
         };
-        recurse_projects(self, &project, &mapped_repository, &link);
+        let mut transaction = Transaction::begin(self, "link", project);
+        install_projects(self, &plan, &graph);
+        let result = apply_to_dependencies(self, &plan, &graph, &mut transaction, &link);
+        print_changes("Linked", transaction.finish(result));
+        record_backups(self, &plan, &graph);
+    }
+
+    fn link_to_package(self, project: &str, kinds: &[DependencyKind]) {
+        let (graph, plan) = get_plan(self, project, kinds);
+        let unlink =
+            |transaction: &mut Transaction, dep: &InstalledDependency, state: LinkState| {
+                if dep.has_backup() && !dep.is_backup_valid() {
+                    return Err(io::Error::other(format!(
+                        "{} is not a backup of {}",
+                        dep.backup.display(),
+                        dep.module
+                    )));
+                }
+                match state {
+                    LinkState::Original => {
+                        println!("=> {} already original", dep.path.display());
+                        return Ok(false);
+                    }
+                    LinkState::Missing if !dep.has_backup() => {
+                        println!("=> Skipping {}: not installed", dep.path.display());
+                        return Ok(false);
+                    }
+                    LinkState::Missing => {}
+                    _ => {
+                        println!("=> Unlinking {}", dep.path.display());
+                        transaction.remove(&dep.path, &dep.source)?;
+                    }
+                }
+                if dep.has_backup() {
+                    println!("=> Restoring {}", dep.backup.display());
+                    transaction.rename(&dep.backup, &dep.path)?;
+                } else {
+                    println!(
+                        "=> No backup of {}, it will be reinstalled",
+                        dep.path.display()
+                    );
+                }
+                Ok(true)
+            };
+        let mut transaction = Transaction::begin(self, "unlink", project);
+        let result = apply_to_dependencies(self, &plan, &graph, &mut transaction, &unlink);
+        print_changes("Unlinked", transaction.finish(result));
+        install_projects(self, &plan, &graph);
+        record_backups(self, &plan, &graph);
     }
 
-    fn link_to_package(self, project: &String) {
-        let mapped_repository = self.map_repository();
-        let unlink = |dep_path: &PathBuf, _: &PathBuf| {
+    fn copy_packages(self, project: &str, kinds: &[DependencyKind], force: bool) {
+        let (graph, plan) = get_plan(self, project, kinds);
+        check_versions(&graph, &plan, force);
+        let copy = |transaction: &mut Transaction, dep: &InstalledDependency, state: LinkState| {
+            match state {
+                LinkState::Copied(0) => {
+                    println!("=> {} already copied", dep.path.display());
+                    return Ok(false);
+                }
+                LinkState::Missing if !dep.has_backup() => {
+                    println!("=> Skipping {}: not installed", dep.path.display());
+                    return Ok(false);
+                }
+                _ => {}
+            }
             println!(
-                "=> Unlinking {} and restoring {}",
-                dep_path.display().to_string(),
-                dep_path.display().to_string() + "_orig"
+                "=> Copying {} to {}",
+                dep.path.display(),
+                dep.source.display()
             );
-            Command::new("rm")
-                .arg("-rf")
-                .arg(dep_path)
-                .output()
-                .expect("error deleting symlink");
-            fs::rename(dep_path.display().to_string() + "_orig", &dep_path)
-                .expect("error renaming directory");
+            set_aside(transaction, dep, &state)?;
+            transaction.copy_package(&dep.source, &dep.path)?;
+            Ok(true)
         };
-        recurse_projects(self, &project, &mapped_repository, &unlink);
+        let mut transaction = Transaction::begin(self, "copy", project);
+        install_projects(self, &plan, &graph);
+        let result = apply_to_dependencies(self, &plan, &graph, &mut transaction, &copy);
+        print_changes("Copied", transaction.finish(result));
+        record_backups(self, &plan, &graph);
     }
 
-    fn copy_packages(self, project: &String) {
-        let mapped_repository = self.map_repository();
-        let copy = |dep_path: &PathBuf, dep_repo_path: &PathBuf| {
+    fn pack_packages(self, project: &str, kinds: &[DependencyKind], force: bool) {
+        let (graph, plan) = get_plan(self, project, kinds);
+        check_versions(&graph, &plan, force);
+        let packs_path = packs_path(self);
+        if !is_dry_run() {
+            fs::create_dir_all(&packs_path).expect("error creating packs directory");
+        }
+        let archives: HashMap<String, PathBuf> = plan
+            .dependencies()
+            .iter()
+            .filter_map(|p| graph.get(p))
+            .map(|ri| {
+                let archive = packs_path.join(archive_name(ri));
+                println!("=> Packing {} to {}", ri.project, archive.display());
+                let packed = yarn_pack(
+                    &self.join(&ri.project).display().to_string(),
+                    &archive.display().to_string(),
+                );
+                if !packed {
+                    println!("Cannot pack {}!", ri.project);
+                    exit(1);
+                }
+                (ri.module.to_string(), archive)
+            })
+            .collect();
+        let installs = RefCell::new(BTreeMap::<PathBuf, Vec<(PathBuf, PathBuf)>>::new());
+        let pack = |transaction: &mut Transaction, dep: &InstalledDependency, state: LinkState| {
+            if matches!(state, LinkState::Missing) && !dep.has_backup() {
+                println!("=> Skipping {}: not installed", dep.path.display());
+                return Ok(false);
+            }
+            let archive = &archives[&dep.module];
             println!(
-                "=> Copying {} to {}",
-                dep_path.display().to_string(),
-                dep_repo_path.display().to_string()
+                "=> Installing {} in {}",
+                archive.display(),
+                dep.path.display()
             );
-            fs::rename(&dep_path, dep_path.display().to_string() + "_orig")
-                .expect("error renaming directory");
-            Command::new("cp")
-                .arg("-r")
-                .arg(dep_repo_path)
-                .arg(dep_path)
-                .output()
-                .expect("error copying directory");
+            set_aside(transaction, dep, &state)?;
+            installs
+                .borrow_mut()
+                .entry(dep.consumer.to_path_buf())
+                .or_default()
+                .push((archive.to_path_buf(), dep.path.to_path_buf()));
+            Ok(true)
         };
-        recurse_projects(self, &project, &mapped_repository, &copy);
+        let mut transaction = Transaction::begin(self, "pack", project);
+        install_projects(self, &plan, &graph);
+        let result = apply_to_dependencies(self, &plan, &graph, &mut transaction, &pack).and_then(
+            |changes| {
+                // a single yarn add per project, as each one relinks the whole project
+                for (consumer, packages) in installs.borrow().iter() {
+                    let (archives, paths): (Vec<PathBuf>, Vec<PathBuf>) =
+                        packages.iter().cloned().unzip();
+                    transaction.install_packages(consumer, &archives, &paths)?;
+                }
+                Ok(changes)
+            },
+        );
+        print_changes("Packed", transaction.finish(result));
+        if !is_dry_run() {
+            let state_path = state_path(self);
+            let mut state = RepoState::read(&state_path);
+            installs.borrow().keys().for_each(|consumer| {
+                state.packed.insert(relative_path(self, consumer));
+            });
+            state.write(&state_path);
+        }
+        record_backups(self, &plan, &graph);
+    }
+
+    fn unpack_packages(self, project: &str, kinds: &[DependencyKind]) {
+        self.link_to_package(project, kinds);
+        // drop the dependencies and binaries yarn add installed with the packed packages
+        let (_, plan) = get_plan(self, project, kinds);
+        let state_path = state_path(self);
+        let mut state = RepoState::read(&state_path);
+        plan.projects
+            .iter()
+            .filter(|p| state.packed.contains(*p))
+            .for_each(|p| yarn_install(&self.join(p).display().to_string()));
+        if !is_dry_run() {
+            state.packed.retain(|p| !plan.projects.contains(p));
+            state.write(&state_path);
+        }
+    }
+
+    fn sync_packages(self, project: &str, kinds: &[DependencyKind], force: bool, debounce: u64) {
+        self.copy_packages(project, kinds, force);
+        let (graph, plan) = get_plan(self, project, kinds);
+        let dependencies: Vec<String> = plan.dependencies().into_iter().cloned().collect();
+        if dependencies.is_empty() {
+            return;
+        }
+        if is_dry_run() {
+            println!("[dry-run] sync {}", dependencies.join(", "));
+            return;
+        }
+        let mut watcher = Watcher::new(
+            dependencies
+                .iter()
+                .map(|p| (p.to_string(), self.join(p)))
+                .collect(),
+            Duration::from_millis(debounce),
+            package_files,
+        );
+        println!("\nSyncing {}...", dependencies.join(", "));
+        loop {
+            watcher.wait().iter().for_each(|changed| {
+                let ri = graph.get(changed).expect("changed project not in graph");
+                graph
+                    .dependents(changed)
+                    .iter()
+                    .filter(|consumer| plan.projects.contains(&consumer.project))
+                    .for_each(|consumer| {
+                        let copy_path = self
+                            .join(&consumer.project)
+                            .join(MODULES_FOLDER)
+                            .join(&ri.module);
+                        if copy_path.is_symlink() || !copy_path.is_dir() {
+                            return;
+                        }
+                        let (updated, removed) = mirror_package(&self.join(changed), &copy_path);
+                        println!(
+                            "=> Synced {}: {} updated, {} removed",
+                            copy_path.display(),
+                            updated,
+                            removed
+                        );
+                    });
+            });
+        }
+    }
+
+    fn update_dependencies(self, project: &str, kinds: &[DependencyKind]) {
+        let (_, plan) = get_plan(self, project, kinds);
+        plan.projects.iter().for_each(|p| {
+            let project_path = self.join(p);
+            remove_node_modules(&project_path);
+            yarn_outdated_upgrade(&project_path);
+        });
+        print_summary("Updated", "updates", &plan);
     }
 
-    fn update_dependencies(self, project: &String) {
-        let mapped_repository = self.map_repository();
-        let mut root_repo_path = PathBuf::from(self);
-        root_repo_path.push(project);
-        yarn_outdated_upgrade(&root_repo_path, &root_repo_path);
-        recurse_projects(self, &project, &mapped_repository, &yarn_outdated_upgrade);
+    fn build_tree(
+        self,
+        project: &str,
+        kinds: &[DependencyKind],
+        since: Option<&str>,
+        jobs: usize,
+        no_cache: bool,
+    ) {
+        let graph = self.map_repository(kinds);
+        let projects = with_script(
+            self,
+            select_projects(self, &graph, project, since, false),
+            "build",
+        );
+        let cache_path = cache_path(self);
+        let mut cache = BuildCache::read(&cache_path);
+        let hashes = hash_projects(self, &graph, &projects);
+        let (fresh, stale): (Vec<String>, Vec<String>) = projects.into_iter().partition(|p| {
+            !no_cache
+                && cache.is_fresh(p, &hashes[p])
+                && has_outputs(self, &self.join(p), &hashes[p])
+        });
+        fresh
+            .iter()
+            .for_each(|p| println!("=> Skipping {}: inputs did not change", p));
+        let outputs: HashMap<String, Vec<String>> = stale
+            .iter()
+            .map(|p| (p.to_string(), output_paths(&self.join(p))))
+            .collect();
+        let (restored, stale): (Vec<String>, Vec<String>) = stale.into_iter().partition(|p| {
+            !no_cache && restore_artifacts(self, &self.join(p), &hashes[p], &outputs[p])
+        });
+        restored.iter().for_each(|p| {
+            println!("=> Restored {} build outputs from cache", p);
+            cache.hashes.insert(p.to_string(), hashes[p].to_string());
+        });
+        let tasks = stale
+            .iter()
+            .map(|p| script_task(self, p, "build", prerequisites(&graph, p, &stale)))
+            .collect();
+        let reports = execute(tasks, jobs);
+        print_reports(&reports);
+        reports
+            .iter()
+            .filter(|r| r.status == TaskStatus::Succeeded)
+            .for_each(|r| {
+                let hash = &hashes[&r.name];
+                store_artifacts(self, &self.join(&r.name), hash, &outputs[&r.name]);
+                cache.hashes.insert(r.name.to_string(), hash.to_string());
+            });
+        cache.write(&cache_path);
+        println!(
+            "\nCache: {} hits, {} restored, {} misses{}",
+            fresh.len(),
+            restored.len(),
+            stale.len(),
+            if no_cache { " (--no-cache)" } else { "" }
+        );
+        if reports.iter().any(|r| r.status != TaskStatus::Succeeded) {
+            exit(1);
+        }
     }
 
-    fn build_tree(self, project: &String) {
-        let mapped_repository = self.map_repository();
-        recurse_projects_no_clean(self, &project, &mapped_repository, &yarn_build_project);
+    fn run_script(
+        self,
+        project: &str,
+        script: &str,
+        kinds: &[DependencyKind],
+        since: Option<&str>,
+        reverse: bool,
+        jobs: usize,
+    ) {
+        let graph = self.map_repository(kinds);
+        let projects = with_script(
+            self,
+            select_projects(self, &graph, project, since, true),
+            script,
+        );
+        let tasks = projects
+            .iter()
+            .map(|p| {
+                let prerequisites = if reverse {
+                    graph
+                        .transitive_dependents(p)
+                        .iter()
+                        .map(|(ri, _)| ri.project.to_string())
+                        .filter(|d| projects.contains(d))
+                        .collect()
+                } else {
+                    prerequisites(&graph, p, &projects)
+                };
+                script_task(self, p, script, prerequisites)
+            })
+            .collect();
+        let reports = execute(tasks, jobs);
+        print_reports(&reports);
+        if reports.iter().any(|r| r.status != TaskStatus::Succeeded) {
+            exit(1);
+        }
     }
 
-    fn clean_tree(self, project: &String) {
-        let mapped_repository = self.map_repository();
-        recurse_clean_projects(self, &project, &mapped_repository);
+    fn exec_command(
+        self,
+        project: &str,
+        command: &[String],
+        kinds: &[DependencyKind],
+        parallel: bool,
+        jobs: usize,
+    ) {
+        let projects = if project.is_empty() {
+            let graph = self.map_repository(kinds);
+            graph
+                .topological_order_of(
+                    &graph
+                        .projects()
+                        .iter()
+                        .map(|ri| ri.project.to_string())
+                        .collect::<Vec<String>>(),
+                )
+                .unwrap_or_else(|cycle| {
+                    println!("Dependency cycle detected: {}", cycle);
+                    exit(1);
+                })
+        } else {
+            get_plan(self, project, kinds).1.projects
+        };
+        let reports = if parallel {
+            let tasks = projects
+                .iter()
+                .map(|p| {
+                    let path = self.join(p);
+                    let command = command.to_vec();
+                    Task::new(p.to_string(), Vec::new(), move || {
+                        match Command::new(&command[0])
+                            .args(&command[1..])
+                            .current_dir(&path)
+                            .output()
+                        {
+                            Ok(output) => task_result(&output),
+                            Err(e) => (false, e.to_string()),
+                        }
+                    })
+                })
+                .collect();
+            let workers = if jobs > 1 { jobs } else { projects.len() };
+            execute(tasks, workers)
+        } else {
+            projects
+                .iter()
+                .map(|p| {
+                    println!("\n=> {}: {}", p, command.join(" "));
+                    let start = Instant::now();
+                    let success = Command::new(&command[0])
+                        .args(&command[1..])
+                        .current_dir(self.join(p))
+                        .status()
+                        .map(|status| status.success())
+                        .unwrap_or_else(|e| {
+                            println!("{}", e);
+                            false
+                        });
+                    TaskReport {
+                        name: p.to_string(),
+                        status: if success {
+                            TaskStatus::Succeeded
+                        } else {
+                            TaskStatus::Failed
+                        },
+                        duration: start.elapsed(),
+                    }
+                })
+                .collect()
+        };
+        print_reports(&reports);
+        if reports.iter().any(|r| r.status != TaskStatus::Succeeded) {
+            exit(1);
+        }
+    }
+
+    fn watch_tree(self, project: &str, kinds: &[DependencyKind], debounce: u64) {
+        let (graph, plan) = get_plan(self, project, kinds);
+        let dependencies: Vec<String> = plan.dependencies().into_iter().cloned().collect();
+        if dependencies.is_empty() {
+            println!("Project {} has no internal dependencies to watch!", project);
+            return;
+        }
+        let mut watcher = Watcher::new(
+            dependencies
+                .iter()
+                .map(|p| (p.to_string(), self.join(p)))
+                .collect(),
+            Duration::from_millis(debounce),
+            source_files,
+        );
+        println!("Watching {}...", dependencies.join(", "));
+        loop {
+            let changed = watcher.wait();
+            println!("\n=> Changed: {}", changed.join(", "));
+            let affected = graph.affected(&changed).unwrap_or_default();
+            let projects = with_script(
+                self,
+                affected
+                    .into_iter()
+                    .filter(|p| dependencies.contains(p))
+                    .collect(),
+                "build",
+            );
+            let tasks = projects
+                .iter()
+                .map(|p| script_task(self, p, "build", prerequisites(&graph, p, &projects)))
+                .collect();
+            print_reports(&execute(tasks, 1));
+            watcher.refresh(&projects);
+            println!("Watching {}...", dependencies.join(", "));
+        }
+    }
+
+    fn clean_tree(self, project: &str, kinds: &[DependencyKind]) {
+        let (_, plan) = get_plan(self, project, kinds);
+        plan.projects
+            .iter()
+            .for_each(|p| remove_node_modules(&self.join(p)));
+        print_summary("Cleaned", "cleanups", &plan);
+    }
+
+    fn restore_backups(self, project: &str, kinds: &[DependencyKind], all: bool) {
+        let graph = self.map_repository(kinds);
+        let projects: Vec<String> = if all {
+            self.get_projects().into_iter().map(|p| p.path).collect()
+        } else {
+            plan_project(&graph, project).projects
+        };
+        let state_path = state_path(self);
+        let mut state = RepoState::read(&state_path);
+        let mut backups = Vec::<(PathBuf, PathBuf)>::new();
+        projects.iter().for_each(|p| {
+            recorded_backups(&state, p)
+                .into_iter()
+                .for_each(|(dep_path, backup)| {
+                    let backup = self.join(backup);
+                    if fs::symlink_metadata(&backup).is_ok() {
+                        backups.push((self.join(dep_path), backup));
+                    }
+                });
+            find_backups(&self.join(p).join(MODULES_FOLDER))
+                .into_iter()
+                .for_each(|backup| {
+                    let name = backup.display().to_string();
+                    let dep_path = PathBuf::from(name.trim_end_matches("_orig"));
+                    backups.push((dep_path, backup));
+                });
+        });
+        let (mut restored, mut failed) = (0, 0);
+        backups.iter().for_each(|(dep_path, backup)| {
+            let key = relative_path(self, dep_path);
+            let mut transaction = Transaction::begin(self, "restore", &key);
+            let result = restore_backup(&mut transaction, self, &graph, dep_path, backup);
+            match transaction.commit(result) {
+                Ok(()) => {
+                    println!("=> Restored {}", dep_path.display());
+                    state.backups.remove(&key);
+                    restored += 1;
+                }
+                Err(e) => {
+                    println!("Could not reconcile {}: {}", dep_path.display(), e);
+                    failed += 1;
+                }
+            }
+        });
+        if !is_dry_run() {
+            // drop the records of backups that no longer exist
+            state
+                .backups
+                .retain(|_, backup| fs::symlink_metadata(self.join(backup)).is_ok());
+            state.write(&state_path);
+        }
+        println!(
+            "\nRestored {} backups, {} could not be reconciled.",
+            restored, failed
+        );
+        if failed > 0 {
+            exit(1);
+        }
+    }
+
+    fn print_status(self, project: &str, kinds: &[DependencyKind]) {
+        let graph = self.map_repository(kinds);
+        let projects = if project.is_empty() {
+            graph
+                .projects()
+                .iter()
+                .map(|ri| ri.project.to_string())
+                .collect()
+        } else {
+            plan_project(&graph, project).projects
+        };
+        let repo_state = RepoState::read(&state_path(self));
+        projects.iter().for_each(|p| {
+            let node_modules_path = self.join(p).join(MODULES_FOLDER);
+            let states: Vec<(InstalledDependency, LinkState)> = graph
+                .dependencies(p)
+                .iter()
+                .map(|ri| {
+                    let dep = InstalledDependency::new(self, p, ri, &repo_state);
+                    let state = dep.state();
+                    (dep, state)
+                })
+                .collect();
+            let mut leftovers: Vec<PathBuf> = states
+                .iter()
+                .filter(|(dep, state)| {
+                    dep.has_backup()
+                        && !matches!(state, LinkState::Linked(_) | LinkState::Copied(_))
+                })
+                .map(|(dep, _)| dep.backup.to_path_buf())
+                .collect();
+            leftovers.extend(find_backups(&node_modules_path));
+            if states.is_empty() && leftovers.is_empty() {
+                return;
+            }
+            println!("{}", p);
+            states
+                .iter()
+                .for_each(|(dep, state)| println!("  {}: {}", dep.module, state));
+            leftovers
+                .iter()
+                .for_each(|backup| println!("  leftover backup: {}", backup.display()));
+        });
+    }
+
+    fn print_graph(
+        self,
+        project: &str,
+        kinds: &[DependencyKind],
+        format: GraphFormat,
+        show_kinds: bool,
+    ) {
+        if project.is_empty() {
+            let graph = self.map_repository(kinds);
+            println!(
+                "{}",
+                export_graph(&graph, &graph.projects(), format, show_kinds)
+            );
+            return;
+        }
+        let (graph, plan) = get_plan(self, project, kinds);
+        let projects: Vec<&RepositoryItem> =
+            plan.projects.iter().filter_map(|p| graph.get(p)).collect();
+        println!("{}", export_graph(&graph, &projects, format, show_kinds));
+    }
+
+    fn print_dependents(self, module: &str, kinds: &[DependencyKind], format: GraphFormat) {
+        let graph = self.map_repository(kinds);
+        let item = graph.find(module).unwrap_or_else(|| {
+            println!("Module {} is not part of the repository!", module);
+            exit(1);
+        });
+        let dependents = graph.transitive_dependents(&item.project);
+        if format == GraphFormat::Json {
+            let json: Vec<serde_json::Value> = dependents
+                .iter()
+                .map(|(ri, depth)| {
+                    serde_json::json!({
+                        "project": ri.project,
+                        "module": ri.module,
+                        "depth": depth
+                    })
+                })
+                .collect();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json).expect("error serializing dependents")
+            );
+            return;
+        }
+        dependents
+            .iter()
+            .for_each(|(ri, depth)| println!("{}\t{}\t{}", depth, ri.project, ri.module));
+    }
+
+    fn print_affected(self, since: &str, kinds: &[DependencyKind], format: GraphFormat) {
+        let graph = self.map_repository(kinds);
+        let changed = changed_projects(self, &graph, since);
+        let affected = graph.affected(&changed).unwrap_or_else(|cycle| {
+            println!("Dependency cycle detected: {}", cycle);
+            exit(1);
+        });
+        let reason = |p: &String| {
+            if changed.contains(p) {
+                "changed"
+            } else {
+                "dependent"
+            }
+        };
+        if format == GraphFormat::Json {
+            let json: Vec<serde_json::Value> = affected
+                .iter()
+                .filter_map(|p| graph.get(p))
+                .map(|ri| {
+                    serde_json::json!({
+                        "project": ri.project,
+                        "module": ri.module,
+                        "reason": reason(&ri.project)
+                    })
+                })
+                .collect();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json).expect("error serializing affected projects")
+            );
+            return;
+        }
+        affected
+            .iter()
+            .filter_map(|p| graph.get(p))
+            .for_each(|ri| println!("{}\t{}\t{}", ri.project, ri.module, reason(&ri.project)));
+    }
+
+    fn print_why(self, project: &str, module: &str, kinds: &[DependencyKind]) {
+        let graph = self.map_repository(kinds);
+        let from = graph.find(project).unwrap_or_else(|| {
+            println!("Project {} is not listed in .meta file!", project);
+            exit(1);
+        });
+        let to = graph.find(module).unwrap_or_else(|| {
+            println!("Module {} is not part of the repository!", module);
+            exit(1);
+        });
+        let paths = graph.paths(&from.project, &to.project);
+        if paths.is_empty() {
+            println!("{} does not depend on {}", from.module, to.module);
+            return;
+        }
+        paths.iter().for_each(|path| {
+            let hops: Vec<String> = path
+                .iter()
+                .map(|(dep, _)| match dep.kind {
+                    DependencyKind::Prod => format!("{}@{}", dep.module, dep.range),
+                    _ => format!("{}@{} ({})", dep.module, dep.range, dep.kind),
+                })
+                .collect();
+            println!("{} -> {}", from.module, hops.join(" -> "));
+        });
+        println!(
+            "\n{} dependency paths from {} to {}",
+            paths.len(),
+            from.module,
+            to.module
+        );
     }
 }
 
 const MODULES_FOLDER: &str = "node_modules";
 
-fn recurse_projects<F>(
+fn collect_meta_projects(
+    root_path: &Path,
+    prefix: &str,
+    projects: &mut Vec<MetaProject>,
+) -> Result<(), MetaFileError> {
+    let meta = MetaFile::read(&root_path.join(prefix))?;
+    for (path, url) in meta.projects.iter().filter(|(p, _)| !meta.is_ignored(p)) {
+        let project_relative_path = Path::new(prefix).join(path).display().to_string();
+        if projects.iter().any(|p| p.path == project_relative_path) {
+            continue;
+        }
+        projects.push(MetaProject::new(project_relative_path.strip(), url.strip()));
+        // follow nested metarepos
+        if root_path
+            .join(&project_relative_path)
+            .join(".meta")
+            .is_file()
+        {
+            collect_meta_projects(root_path, &project_relative_path, projects)?;
+        }
+    }
+    Ok(())
+}
+
+fn get_plan(
     root_path: &PathBuf,
-    project: &String,
-    mapped_repository: &HashMap<String, RepositoryItem>,
-    function: &F,
-) where
-    F: Fn(&PathBuf, &PathBuf),
-{
-    let mut root_project_path = PathBuf::from(root_path);
-    root_project_path.push(project);
-    let mut node_modules_path = PathBuf::from(&root_project_path);
-    node_modules_path.push(MODULES_FOLDER);
-    Command::new("rm")
-        .arg("-rf")
-        .arg(&node_modules_path)
-        .output()
-        .expect("error removing node_modules directory");
-    yarn_install(&root_project_path.display().to_string());
-    mapped_repository[project]
-        .dependencies
+    project: &str,
+    kinds: &[DependencyKind],
+) -> (DependencyGraph, TraversalPlan) {
+    let graph = root_path.map_repository(kinds);
+    let plan = plan_project(&graph, project);
+    (graph, plan)
+}
+
+fn plan_project(graph: &DependencyGraph, project: &str) -> TraversalPlan {
+    if graph.get(project).is_none() {
+        println!("Project {} is not listed in .meta file!", project);
+        exit(1);
+    }
+    graph.plan(project).unwrap_or_else(|cycle| {
+        println!("Dependency cycle detected: {}", cycle);
+        exit(1);
+    })
+}
+
+fn changed_projects(root_path: &Path, graph: &DependencyGraph, since: &str) -> Vec<String> {
+    eprintln!("Looking for changes since {}...", since);
+    graph
+        .projects()
         .iter()
-        .for_each(|dep| {
-            let mut dep_path = PathBuf::from(&node_modules_path);
-            dep_path.push(dep);
-            dep_path.as_path().exists().then(|| {
-                mapped_repository
-                    .values()
-                    .find(|ri| &ri.module == dep)
-                    .map(|ri| {
-                        // link nested dependencies
-                        recurse_projects(root_path, &ri.project, &mapped_repository, function);
-                        // link root dependencies
-                        let mut dep_repo_path = PathBuf::from(root_path);
-                        dep_repo_path.push(&ri.project);
-                        dep_repo_path
-                            .as_path()
-                            .is_dir()
-                            .then(|| function(&dep_path, &dep_repo_path));
-                    });
-            });
-        });
+        .filter(|ri| {
+            let project_path = root_path.join(&ri.project).display().to_string();
+            git_changed_files(&project_path, &since.to_string()).map_or_else(
+                || {
+                    eprintln!(
+                        "Cannot compare {} with {}, considering it changed",
+                        ri.project, since
+                    );
+                    true
+                },
+                |files| !files.is_empty(),
+            )
+        })
+        .map(|ri| ri.project.to_string())
+        .collect()
 }
 
-fn recurse_projects_no_clean<F>(
-    root_path: &PathBuf,
-    project: &String,
-    mapped_repository: &HashMap<String, RepositoryItem>,
-    function: &F,
-) where
-    F: Fn(&PathBuf, &PathBuf),
-{
-    let mut root_project_path = PathBuf::from(root_path);
-    root_project_path.push(project);
-    let mut node_modules_path = PathBuf::from(&root_project_path);
-    node_modules_path.push(MODULES_FOLDER);
-    mapped_repository[project]
-        .dependencies
+/// Projects to operate on: the planned tree of the project, narrowed to the projects
+/// affected by changes since the given ref, or every affected project when no project
+/// is given.
+fn select_projects(
+    root_path: &Path,
+    graph: &DependencyGraph,
+    project: &str,
+    since: Option<&str>,
+    include_root: bool,
+) -> Vec<String> {
+    let affected = since.map(|since| {
+        graph
+            .affected(&changed_projects(root_path, graph, since))
+            .unwrap_or_else(|cycle| {
+                println!("Dependency cycle detected: {}", cycle);
+                exit(1);
+            })
+    });
+    if project.is_empty() {
+        if let Some(affected) = affected {
+            return affected;
+        }
+    }
+    let plan = plan_project(graph, project);
+    plan.projects
         .iter()
-        .for_each(|dep| {
-            let mut dep_path = PathBuf::from(&node_modules_path);
-            dep_path.push(dep);
-            dep_path.as_path().exists().then(|| {
-                mapped_repository
-                    .values()
-                    .find(|ri| &ri.module == dep)
-                    .map(|ri| {
-                        // link nested dependencies
-                        recurse_projects_no_clean(
-                            root_path,
-                            &ri.project,
-                            &mapped_repository,
-                            function,
-                        );
-                        // link root dependencies
-                        let mut dep_repo_path = PathBuf::from(root_path);
-                        dep_repo_path.push(&ri.project);
-                        dep_repo_path
-                            .as_path()
-                            .is_dir()
-                            .then(|| function(&dep_path, &dep_repo_path));
-                    });
-            });
+        .filter(|p| include_root || **p != plan.root)
+        .filter(|p| affected.as_ref().is_none_or(|a| a.contains(p)))
+        .cloned()
+        .collect()
+}
+
+/// Keeps the projects defining the script, reporting the skipped ones.
+fn with_script(root_path: &Path, projects: Vec<String>, script: &str) -> Vec<String> {
+    projects
+        .into_iter()
+        .filter(|p| {
+            let has_script = PackageJson::read(p, &root_path.join(p))
+                .map(|pkg| pkg.scripts.contains_key(script))
+                .unwrap_or(false);
+            if !has_script {
+                println!("=> Skipping {}: no {} script", p, script);
+            }
+            has_script
+        })
+        .collect()
+}
+
+/// Input hashes of the projects and of everything they depend on.
+fn hash_projects(
+    root_path: &Path,
+    graph: &DependencyGraph,
+    projects: &[String],
+) -> HashMap<String, String> {
+    let mut hashes = HashMap::<String, String>::new();
+    graph
+        .topological_order_of(projects)
+        .unwrap_or_default()
+        .iter()
+        .for_each(|p| {
+            let dependency_hashes: Vec<String> = graph
+                .dependencies(p)
+                .iter()
+                .filter_map(|d| hashes.get(&d.project).cloned())
+                .collect();
+            let hash = hash_project(&root_path.join(p), &dependency_hashes);
+            hashes.insert(p.to_string(), hash);
         });
+    hashes
 }
 
-fn recurse_clean_projects(
-    root_path: &PathBuf,
-    project: &String,
-    mapped_repository: &HashMap<String, RepositoryItem>,
-) {
-    let mut root_project_path = PathBuf::from(root_path);
-    root_project_path.push(project);
-    let mut node_modules_path = PathBuf::from(&root_project_path);
+fn script_task(root_path: &Path, project: &str, script: &str, prerequisites: Vec<String>) -> Task {
+    let path = root_path.join(project).display().to_string();
+    let script = script.to_string();
+    Task::new(project.to_string(), prerequisites, move || {
+        task_result(&yarn_run_output(&path, &script))
+    })
+}
+
+/// Status and joined stdout and stderr of a command run by a task.
+fn task_result(output: &Output) -> (bool, String) {
+    let text = String::from_utf8_lossy(&output.stdout).to_string()
+        + &String::from_utf8_lossy(&output.stderr);
+    (output.status.success(), text)
+}
+
+/// Selected projects a project transitively depends on.
+fn prerequisites(graph: &DependencyGraph, project: &str, selected: &[String]) -> Vec<String> {
+    graph
+        .topological_order(project)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|p| p != project && selected.contains(p))
+        .collect()
+}
+
+fn check_versions(graph: &DependencyGraph, plan: &TraversalPlan, force: bool) {
+    let mut mismatches = Vec::<String>::new();
+    plan.projects.iter().for_each(|project| {
+        graph
+            .edges(project)
+            .iter()
+            .for_each(|(dep, ri)| match ri.version.satisfies(&dep.range) {
+                Some(false) => mismatches.push(format!(
+                    "{} requires {}@{} but local version is {}",
+                    project, dep.module, dep.range, ri.version
+                )),
+                None => println!(
+                    "(!) Cannot check {}@{} against local version {} in {}",
+                    dep.module, dep.range, ri.version, project
+                ),
+                Some(true) => {}
+            });
+    });
+    if mismatches.is_empty() {
+        return;
+    }
+    mismatches.iter().for_each(|m| println!("(!) {}", m));
+    if !force {
+        println!(
+            "\nLocal packages do not satisfy the declared ranges, use --force to proceed anyway."
+        );
+        exit(1);
+    }
+    println!("\nProceeding anyway because of --force.\n");
+}
+
+fn print_summary(verb: &str, operation: &str, plan: &TraversalPlan) {
+    println!(
+        "\n{} {} projects, {} redundant {} saved.",
+        verb,
+        plan.projects.len(),
+        plan.saved_visits(),
+        operation
+    );
+}
+
+fn remove_node_modules(project_path: &Path) {
+    let mut node_modules_path = PathBuf::from(project_path);
     node_modules_path.push(MODULES_FOLDER);
-    Command::new("rm")
-        .arg("-rf")
-        .arg(&node_modules_path)
-        .output()
-        .expect("error removing node_modules directory");
-    mapped_repository[project]
-        .dependencies
+    operations::remove_all(&node_modules_path).expect("error removing node_modules directory");
+}
+
+/// An internal dependency as installed in the node_modules of a project.
+struct InstalledDependency {
+    module: String,
+    /// Project depending on the package.
+    consumer: PathBuf,
+    /// Location in the project's node_modules.
+    path: PathBuf,
+    /// Local project providing the package.
+    source: PathBuf,
+    /// Where the original package is kept while it is replaced.
+    backup: PathBuf,
+}
+
+impl InstalledDependency {
+    /// Locates the dependency and its backup, as recorded in the state file or, for a
+    /// package not backed up yet, in the backups folder of the project.
+    fn new(
+        root_path: &Path,
+        project: &str,
+        dependency: &RepositoryItem,
+        state: &RepoState,
+    ) -> Self {
+        let path = root_path
+            .join(project)
+            .join(MODULES_FOLDER)
+            .join(&dependency.module);
+        let backup = state
+            .backups
+            .get(&relative_path(root_path, &path))
+            .map(|backup| root_path.join(backup))
+            .unwrap_or_else(|| project_backups_path(root_path, project).join(&dependency.module));
+        Self {
+            module: dependency.module.to_string(),
+            consumer: root_path.join(project),
+            path,
+            source: root_path.join(&dependency.project),
+            backup,
+        }
+    }
+
+    fn has_backup(&self) -> bool {
+        fs::symlink_metadata(&self.backup).is_ok()
+    }
+
+    /// Whether the backup holds the package it stands for.
+    fn is_backup_valid(&self) -> bool {
+        PackageJson::read(&self.module, &self.backup)
+            .is_ok_and(|pkg| pkg.name.as_deref() == Some(self.module.as_str()))
+    }
+
+    /// Backup left next to the package by previous versions.
+    fn legacy_backup(&self) -> PathBuf {
+        PathBuf::from(self.path.display().to_string() + "_orig")
+    }
+
+    fn state(&self) -> LinkState {
+        if let Ok(target) = fs::read_link(&self.path) {
+            return if self.path.exists() {
+                LinkState::Linked(target)
+            } else {
+                LinkState::Broken(format!("{} does not exist", target.display()))
+            };
+        }
+        if !self.path.is_dir() {
+            return LinkState::Missing;
+        }
+        if self.has_backup() {
+            let (outdated, removed) = package_drift(&self.source, &self.path);
+            return LinkState::Copied(outdated.len() + removed.len());
+        }
+        LinkState::Original
+    }
+}
+
+fn backups_path(root_path: &Path) -> PathBuf {
+    let mut path = PathBuf::from(root_path);
+    path.push(".repo");
+    path.push("backups");
+    path
+}
+
+/// Backups of a project, laid out like its node_modules so that the backups of nested
+/// projects never share a folder with them.
+fn project_backups_path(root_path: &Path, project: &str) -> PathBuf {
+    backups_path(root_path).join(project).join(MODULES_FOLDER)
+}
+
+fn packs_path(root_path: &Path) -> PathBuf {
+    let mut path = PathBuf::from(root_path);
+    path.push(".repo");
+    path.push("packs");
+    path
+}
+
+/// Tarball name `npm pack` gives a package, e.g. `scope-name-1.0.0.tgz`.
+fn archive_name(ri: &RepositoryItem) -> String {
+    format!(
+        "{}-{}.tgz",
+        ri.module.trim_start_matches('@').replace('/', "-"),
+        ri.version
+    )
+}
+
+fn state_path(root_path: &Path) -> PathBuf {
+    let mut path = PathBuf::from(root_path);
+    path.push(".repo");
+    path.push("state.json");
+    path
+}
+
+/// Updates the state file with the backups of the planned projects.
+fn record_backups(root_path: &Path, plan: &TraversalPlan, graph: &DependencyGraph) {
+    if is_dry_run() {
+        return;
+    }
+    let state_path = state_path(root_path);
+    let mut state = RepoState::read(&state_path);
+    plan.projects.iter().for_each(|project| {
+        graph.dependencies(project).iter().for_each(|ri| {
+            let dep = InstalledDependency::new(root_path, project, ri, &state);
+            let key = relative_path(root_path, &dep.path);
+            if dep.has_backup() {
+                state
+                    .backups
+                    .insert(key, relative_path(root_path, &dep.backup));
+            } else {
+                state.backups.remove(&key);
+            }
+        });
+    });
+    state.write(&state_path);
+}
+
+fn relative_path(root_path: &Path, path: &Path) -> String {
+    path.strip_prefix(root_path)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// Backups found in a node_modules folder, including scoped packages and the
+/// node_modules of installed packages.
+fn find_backups(node_modules_path: &Path) -> Vec<PathBuf> {
+    let mut backups = Vec::<PathBuf>::new();
+    collect_backups(node_modules_path, &mut backups);
+    backups.sort();
+    backups
+}
+
+fn collect_backups(node_modules_path: &Path, backups: &mut Vec<PathBuf>) {
+    let entries = |dir: &Path| -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|entries| entries.flatten().map(|e| e.path()).collect())
+            .unwrap_or_default()
+    };
+    entries(node_modules_path)
+        .into_iter()
+        .flat_map(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name.starts_with('@') {
+                entries(&path)
+            } else {
+                vec![path]
+            }
+        })
+        .for_each(|path| {
+            if is_backup(&path) {
+                backups.push(path);
+            } else if !path.is_symlink() && path.is_dir() {
+                collect_backups(&path.join(MODULES_FOLDER), backups);
+            }
+        });
+}
+
+/// Backups recorded in the state file for the dependencies of a project, as paths
+/// relative to the root of the dependency and of its backup.
+fn recorded_backups(state: &RepoState, project: &str) -> Vec<(String, String)> {
+    let prefix = Path::new(project).join(MODULES_FOLDER);
+    state
+        .backups
         .iter()
-        .for_each(|dep| {
-            mapped_repository
-                .values()
-                .find(|ri| &ri.module == dep)
-                .map(|ri| {
-                    // clean nested dependencies
-                    recurse_clean_projects(root_path, &ri.project, &mapped_repository);
-                });
+        .filter(|(dep_path, _)| Path::new(dep_path).starts_with(&prefix))
+        .map(|(dep_path, backup)| (dep_path.to_string(), backup.to_string()))
+        .collect()
+}
+
+/// Puts a backup back in place of the link or copy that replaced it. A directory that
+/// is not a copy of the local package is kept.
+fn restore_backup(
+    transaction: &mut Transaction,
+    root_path: &Path,
+    graph: &DependencyGraph,
+    dep_path: &Path,
+    backup: &Path,
+) -> io::Result<()> {
+    match fs::symlink_metadata(dep_path) {
+        Ok(metadata) if metadata.is_symlink() => {
+            transaction.remove(dep_path, &fs::read_link(dep_path)?)?
+        }
+        Ok(metadata) if metadata.is_dir() => {
+            let source = module_name(dep_path)
+                .and_then(|module| graph.get_by_module(&module))
+                .map(|ri| ri.project.to_string())
+                .filter(|project| is_copy_of(dep_path, project, graph))
+                .ok_or_else(|| {
+                    io::Error::other("installed package is not a link or copy of a local project")
+                })?;
+            transaction.remove(dep_path, &root_path.join(source))?
+        }
+        Ok(_) => return Err(io::Error::other("installed package is not a directory")),
+        Err(_) => {}
+    }
+    transaction.rename(backup, dep_path)
+}
+
+/// Module name of a package installed at the given path, scope included.
+fn module_name(dep_path: &Path) -> Option<String> {
+    let name = dep_path.file_name()?.to_string_lossy().to_string();
+    let parent = dep_path
+        .parent()?
+        .file_name()?
+        .to_string_lossy()
+        .to_string();
+    if parent.starts_with('@') {
+        Some(format!("{}/{}", parent, name))
+    } else {
+        Some(name)
+    }
+}
+
+fn is_copy_of(dep_path: &Path, project: &str, graph: &DependencyGraph) -> bool {
+    let installed = PackageJson::read(project, dep_path);
+    match (installed, graph.get(project)) {
+        (Ok(pkg), Some(ri)) => {
+            pkg.name.as_deref() == Some(ri.module.as_str())
+                && pkg.version.as_deref() == Some(ri.version.as_str())
+        }
+        _ => false,
+    }
+}
+
+fn is_backup(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with("_orig"))
+}
+
+/// Installs the planned projects whose node_modules or one of its internal
+/// dependencies is missing.
+fn install_projects(root_path: &Path, plan: &TraversalPlan, graph: &DependencyGraph) {
+    let state_path = state_path(root_path);
+    let mut state = RepoState::read(&state_path);
+    let (missing, installed): (Vec<&String>, Vec<&String>) = plan
+        .projects
+        .iter()
+        .partition(|p| needs_install(root_path, graph, &state, p));
+    missing.iter().for_each(|project| {
+        let project_path = root_path.join(project);
+        remove_node_modules(&project_path);
+        // backups are outdated once the originals are installed again
+        recorded_backups(&state, project)
+            .iter()
+            .for_each(|(_, backup)| {
+                operations::remove_all(&root_path.join(backup)).expect("error removing backup")
+            });
+        operations::remove_all(&project_backups_path(root_path, project))
+            .expect("error removing backups");
+        yarn_install(&project_path.display().to_string());
+    });
+    if !is_dry_run() && missing.iter().any(|p| state.packed.contains(*p)) {
+        // a fresh install leaves nothing of the packed packages for unpack to drop
+        missing.iter().for_each(|p| {
+            state.packed.remove(*p);
         });
+        state.write(&state_path);
+    }
+    // the recursive traversal installed a project on every visit
+    println!(
+        "\nInstalled {} projects, {} already installed, {} redundant installs saved.",
+        missing.len(),
+        installed.len(),
+        plan.visits - missing.len()
+    );
 }
 
-fn iterate_projects<I, P, F>(projects: &Vec<I>, predicate: P, f: F)
+/// Dependency kinds the package manager installs; peer dependencies are left to the
+/// consumer.
+const INSTALLED_KINDS: [DependencyKind; 3] = [
+    DependencyKind::Prod,
+    DependencyKind::Dev,
+    DependencyKind::Optional,
+];
+
+fn needs_install(
+    root_path: &Path,
+    graph: &DependencyGraph,
+    state: &RepoState,
+    project: &str,
+) -> bool {
+    let node_modules_path = root_path.join(project).join(MODULES_FOLDER);
+    let has_dependencies = PackageJson::read(project, &root_path.join(project)).is_ok_and(|pkg| {
+        INSTALLED_KINDS
+            .iter()
+            .any(|kind| !pkg.dependencies_of(*kind).is_empty())
+    });
+    (has_dependencies && !node_modules_path.is_dir())
+        || graph
+            .edges(project)
+            .iter()
+            .filter(|(dep, _)| INSTALLED_KINDS.contains(&dep.kind))
+            .any(|(_, ri)| {
+                let dep = InstalledDependency::new(root_path, project, ri, state);
+                fs::symlink_metadata(&dep.path).is_err() && !dep.has_backup()
+            })
+}
+
+/// Applies the function to the internal dependencies of the planned projects with
+/// their current state, stopping at the first error. Returns how many dependencies
+/// were changed and how many were already in place.
+fn apply_to_dependencies<F>(
+    root_path: &Path,
+    plan: &TraversalPlan,
+    graph: &DependencyGraph,
+    transaction: &mut Transaction,
+    function: &F,
+) -> io::Result<(usize, usize)>
 where
-    P: FnMut(&&I) -> bool,
-    F: FnMut(&I),
+    F: Fn(&mut Transaction, &InstalledDependency, LinkState) -> io::Result<bool>,
 {
-    return projects.iter().filter(predicate).for_each(f);
+    let repo_state = RepoState::read(&state_path(root_path));
+    let (mut changed, mut unchanged) = (0, 0);
+    for project in &plan.projects {
+        for ri in graph.dependencies(project) {
+            let dep = InstalledDependency::new(root_path, project, ri, &repo_state);
+            if !dep.source.is_dir() {
+                continue;
+            }
+            migrate_backup(transaction, &dep)?;
+            let state = match dep.state() {
+                // on a dry run projects were not actually installed
+                LinkState::Missing if is_dry_run() && !dep.has_backup() => LinkState::Original,
+                state => state,
+            };
+            if function(transaction, &dep, state)? {
+                changed += 1;
+            } else {
+                unchanged += 1;
+            }
+        }
+    }
+    Ok((changed, unchanged))
 }
 
-fn get_package_json(path: &PathBuf) -> serde_json::Value {
-    let mut project_path = PathBuf::from(path);
-    project_path.push("package.json");
-    return std::fs::read_to_string(project_path).map_or_else(
-        |_| serde_json::from_str("{}").expect("...not well formatted!"),
-        |c| serde_json::from_str(&c).expect("...not well formatted!"),
+/// Moves a backup left in node_modules by previous versions to the backups folder.
+fn migrate_backup(transaction: &mut Transaction, dep: &InstalledDependency) -> io::Result<()> {
+    let legacy = dep.legacy_backup();
+    if fs::symlink_metadata(&legacy).is_err() {
+        return Ok(());
+    }
+    if dep.has_backup() {
+        println!(
+            "=> Keeping {}: {} already exists",
+            legacy.display(),
+            dep.backup.display()
+        );
+        return Ok(());
+    }
+    println!(
+        "=> Migrating {} to {}",
+        legacy.display(),
+        dep.backup.display()
     );
+    transaction.rename(&legacy, &dep.backup)
 }
 
-fn get_cross_dependencies(package: &serde_json::Value, modules: &Vec<String>) -> Vec<String> {
-    let dependencies_section = package["dependencies"].as_object();
-    let dependencies = dependencies_section.map_or_else(
-        || Vec::<String>::new(),
-        |d| d.keys().map(|k| k.to_string()).collect(),
+fn print_changes(verb: &str, (changed, unchanged): (usize, usize)) {
+    println!(
+        "\n{} {} dependencies, {} already in place.",
+        verb, changed, unchanged
     );
+}
 
-    return dependencies
-        .iter()
-        .filter(|d| modules.contains(d))
-        .map(|d| d.strip())
-        .collect();
+/// Makes room for a local package: backs up the original one, or removes the link or
+/// copy that replaced it.
+fn set_aside(
+    transaction: &mut Transaction,
+    dep: &InstalledDependency,
+    state: &LinkState,
+) -> io::Result<()> {
+    match state {
+        LinkState::Original => transaction.rename(&dep.path, &dep.backup),
+        LinkState::Missing => Ok(()),
+        _ => transaction.remove(&dep.path, &dep.source),
+    }
 }
 
-fn yarn_build_project(_: &PathBuf, dep_repo_path: &PathBuf) {
-    let path = dep_repo_path.display().to_string();
-    let message = format!("I'm in repo {}", path);
-    println!();
-    println!("{}", "#".repeat(message.len()));
-    println!("{}", message);
-    println!("{}", "-".repeat(message.len()));
-    yarn_build(&path);
+fn iterate_projects<I, P, F>(projects: &[I], predicate: P, f: F)
+where
+    P: FnMut(&&I) -> bool,
+    F: FnMut(&I),
+{
+    projects.iter().filter(predicate).for_each(f)
+}
+
+fn get_cross_dependencies(
+    package: &PackageJson,
+    modules: &[String],
+    kinds: &[DependencyKind],
+) -> Vec<Dependency> {
+    let mut dependencies = Vec::<Dependency>::new();
+    for kind in kinds {
+        for (module, range) in package.dependencies_of(*kind) {
+            if modules.contains(module) && !dependencies.iter().any(|d| &d.module == module) {
+                dependencies.push(Dependency::new(module.strip(), *kind, range.strip()));
+            }
+        }
+    }
+    dependencies
 }
 
-fn yarn_outdated_upgrade(_: &PathBuf, dep_repo_path: &PathBuf) {
+fn yarn_outdated_upgrade(dep_repo_path: &Path) {
     let path = dep_repo_path.display().to_string();
     let message = format!("I'm in repo {}", path);
     println!();
@@ -326,7 +1484,7 @@ fn yarn_outdated_upgrade(_: &PathBuf, dep_repo_path: &PathBuf) {
         serde_json::from_str(&outdated).expect("json not formatted");
     let data_value: &serde_json::Value = &root_value.as_object().expect("")["data"];
     let body_value: &serde_json::Value = &data_value.as_object().expect("")["body"];
-    let packages: &Vec<serde_json::Value> = &body_value.as_array().expect("");
+    let packages: &Vec<serde_json::Value> = body_value.as_array().expect("");
     packages.iter().for_each(|p| {
         let package = p.as_array().expect("");
         let name = package[0].as_str().expect("");
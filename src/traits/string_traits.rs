@@ -7,9 +7,3 @@ impl StringExtension for &String {
         self.to_string().trim().replace("\"", "")
     }
 }
-
-impl StringExtension for &serde_json::Value {
-    fn strip(self) -> String {
-        self.to_string().trim().replace("\"", "")
-    }
-}
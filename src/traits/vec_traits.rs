@@ -15,7 +15,7 @@ impl VecExtension for Vec<u8> {
             .map(|s| {
                 println!("{}", s);
             })
-            .map_err(|e| println!("Parse error: {}", e.to_string()))
+            .map_err(|e| println!("Parse error: {}", e))
             .unwrap();
     }
 
@@ -23,48 +23,41 @@ impl VecExtension for Vec<u8> {
         String::from_utf8(self)
             .map(|s| {
                 println!("{}", s);
-                if s.len() > 0 {
+                if !s.is_empty() {
                     exit(1);
                 }
             })
-            .map_err(|e| println!("Parse error: {}", e.to_string()))
+            .map_err(|e| println!("Parse error: {}", e))
             .unwrap();
     }
 
     fn get_string(self) -> String {
-        return String::from_utf8(self)
-            .map(|s| {
-                return s;
-            })
-            .map_err(|_e| {
-                return "";
-            })
-            .unwrap();
+        String::from_utf8(self).map_err(|_e| "").unwrap()
     }
 
     fn get_string_or_die(self) -> String {
-        return String::from_utf8(self).expect("Parse error!");
+        String::from_utf8(self).expect("Parse error!")
     }
 
     fn log_and_get_string(self) -> String {
-        return String::from_utf8(self)
+        String::from_utf8(self)
             .map(|s| {
                 println!("{}", s);
-                return s;
+                s
             })
             .map_err(|e| {
-                println!("Parse error: {}", e.to_string());
-                return "";
+                println!("Parse error: {}", e);
+                ""
             })
-            .unwrap();
+            .unwrap()
     }
 
     fn log_and_get_string_or_die(self) -> String {
-        return String::from_utf8(self)
+        String::from_utf8(self)
             .map(|s| {
                 println!("{}", s);
-                return s;
+                s
             })
-            .expect("Parse error!");
+            .expect("Parse error!")
     }
 }
@@ -1,33 +1,173 @@
+use semver::{Version, VersionReq};
+
 pub trait Versioning {
-    fn is_more_recent(self, than: &str) -> bool;
-    fn split_dot_version(self) -> Vec<u8>;
-    fn strip_dash_variant(self) -> String;
+    fn is_more_recent(&self, than: &str) -> bool;
+    fn split_dot_version(&self) -> Vec<u8>;
+    fn strip_dash_variant(&self) -> String;
+    fn satisfies(&self, range: &str) -> Option<bool>;
 }
 
-impl Versioning for &str {
-    fn is_more_recent(self, than: &str) -> bool {
+impl Versioning for str {
+    fn is_more_recent(&self, than: &str) -> bool {
         let base_version = self.split_dot_version();
         let than_version = than.split_dot_version();
-        for i in 0..base_version.len() {
-            return &base_version[i] > &than_version[i];
+        if let (Some(base), Some(than)) = (base_version.first(), than_version.first()) {
+            return base > than;
         }
-        return false;
+        false
     }
 
-    fn split_dot_version(self) -> Vec<u8> {
-        return self
-            .to_string()
-            .split('.')
+    fn split_dot_version(&self) -> Vec<u8> {
+        self.split('.')
             .map(|v| v.strip_dash_variant().parse::<u8>().unwrap())
-            .collect::<Vec<u8>>();
+            .collect::<Vec<u8>>()
+    }
+
+    fn strip_dash_variant(&self) -> String {
+        self.split('-').next().unwrap_or("0").to_string()
     }
 
-    fn strip_dash_variant(self) -> String {
-        return self
-            .to_string()
-            .split('-')
-            .next()
-            .unwrap_or("0")
-            .to_string();
+    /// Checks the version against an npm range, `None` when either one is not semver
+    /// (tags, urls, `file:`/`link:`/`workspace:` protocols).
+    fn satisfies(&self, range: &str) -> Option<bool> {
+        let version = Version::parse(self.trim().trim_start_matches('v')).ok()?;
+        let alternatives = range
+            .split("||")
+            .map(npm_range_to_requirement)
+            .collect::<Option<Vec<VersionReq>>>()?;
+        Some(alternatives.iter().any(|r| r.matches(&version)))
+    }
+}
+
+fn npm_range_to_requirement(range: &str) -> Option<VersionReq> {
+    let range = range.trim();
+    if range.is_empty() || range == "*" || range == "x" {
+        return Some(VersionReq::STAR);
+    }
+    if range.contains(':') || range.contains('/') {
+        return None;
+    }
+    if let Some((from, to)) = range.split_once(" - ") {
+        return VersionReq::parse(&format!(">={}, <={}", from.trim(), to.trim())).ok();
+    }
+    let mut comparators = Vec::<String>::new();
+    let mut operator = String::new();
+    for token in range.split_whitespace() {
+        if token.chars().all(|c| "<>=~^".contains(c)) {
+            operator = token.to_string();
+            continue;
+        }
+        let token = format!("{}{}", operator, token);
+        operator.clear();
+        let version = token.trim_start_matches(|c| "<>=~^".contains(c));
+        let prefix = &token[..token.len() - version.len()];
+        let version = version.trim_start_matches('v');
+        // a bare npm version is an exact match, a bare rust one is a caret
+        let prefix = if prefix.is_empty() { "=" } else { prefix };
+        comparators.push(format!("{}{}", prefix, version));
+    }
+    VersionReq::parse(&comparators.join(", ")).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn satisfies_npm_ranges() {
+        let cases = [
+            // carets and tildes
+            ("1.5.0", "^1.2.0", true),
+            ("2.0.0", "^1.2.0", false),
+            ("0.2.5", "^0.2.1", true),
+            ("0.3.0", "^0.2.1", false),
+            ("1.2.9", "~1.2.0", true),
+            ("1.3.0", "~1.2.0", false),
+            // bare versions are exact
+            ("1.2.3", "1.2.3", true),
+            ("1.2.4", "1.2.3", false),
+            ("1.2.3", "v1.2.3", true),
+            ("1.2.3", "=1.2.3", true),
+            // wildcards
+            ("3.1.4", "*", true),
+            ("3.1.4", "", true),
+            ("3.1.4", "x", true),
+            ("1.9.0", "1.x", true),
+            ("2.0.0", "1.x", false),
+            // comparators, with and without spaces after the operator
+            ("1.5.0", ">=1.0.0 <2.0.0", true),
+            ("2.0.0", ">=1.0.0 <2.0.0", false),
+            ("1.5.0", ">= 1.0.0 < 2.0.0", true),
+            ("0.9.0", ">= 1.0.0 < 2.0.0", false),
+            ("1.0.1", ">1.0.0", true),
+            ("1.0.0", ">1.0.0", false),
+            // hyphen ranges are inclusive
+            ("1.0.0", "1.0.0 - 2.0.0", true),
+            ("2.0.0", "1.0.0 - 2.0.0", true),
+            ("2.0.1", "1.0.0 - 2.0.0", false),
+            // alternatives
+            ("2.1.0", "^1.0.0 || ^2.0.0", true),
+            ("3.0.0", "^1.0.0 || ^2.0.0", false),
+            ("1.2.3", "1.2.3 || >=2.0.0", true),
+            // prerelease versions only match ranges on the same version
+            ("1.3.0-beta.1", "^1.2.0", false),
+            ("1.2.0-beta.2", "^1.2.0-beta.1", true),
+            ("1.2.0-rc.1", "1.2.0-rc.1", true),
+            ("1.2.0", "1.2.0-rc.1", false),
+            ("1.2.0", ">=1.2.0-rc.1", true),
+            // versions are trimmed and may start with v
+            (" v1.2.3 ", "^1.0.0", true),
+        ];
+        for (version, range, expected) in cases {
+            assert_eq!(
+                version.satisfies(range),
+                Some(expected),
+                "{} in {}",
+                version,
+                range
+            );
+        }
+    }
+
+    #[test]
+    fn satisfies_nothing_for_non_semver_ranges_and_versions() {
+        let cases = [
+            ("1.0.0", "file:../lib"),
+            ("1.0.0", "link:../lib"),
+            ("1.0.0", "workspace:*"),
+            ("1.0.0", "npm:other@^1.0.0"),
+            ("1.0.0", "github:io/lib"),
+            ("1.0.0", "io/lib"),
+            ("1.0.0", "latest"),
+            ("1.0.0", "^1.0.0 || next"),
+            ("1.0", "^1.0.0"),
+            ("latest", "^1.0.0"),
+            ("", "*"),
+        ];
+        for (version, range) in cases {
+            assert_eq!(version.satisfies(range), None, "{} in {}", version, range);
+        }
+    }
+
+    #[test]
+    fn npm_ranges_translate_to_requirements() {
+        let cases = [
+            ("^1.2.0", Some("^1.2.0")),
+            ("1.2.3", Some("=1.2.3")),
+            ("v1.2.3", Some("=1.2.3")),
+            (">= 1.0.0 < 2.0.0", Some(">=1.0.0, <2.0.0")),
+            ("1.0.0 - 2.0.0", Some(">=1.0.0, <=2.0.0")),
+            ("*", Some("*")),
+            ("file:../lib", None),
+            ("latest", None),
+        ];
+        for (range, expected) in cases {
+            assert_eq!(
+                npm_range_to_requirement(range).map(|r| r.to_string()),
+                expected.map(|e| VersionReq::parse(e).unwrap().to_string()),
+                "{}",
+                range
+            );
+        }
     }
 }